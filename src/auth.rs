@@ -0,0 +1,246 @@
+use crate::error::VpnError;
+use crate::http::{self, HttpResponse, Timeouts};
+use crate::keys::{self, SecretKey};
+use crate::browser;
+use crate::tls::TlsSettings;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/** @brief Tokens expiring within this many seconds are refreshed before use. */
+pub const REFRESH_MARGIN_SECS: u64 = 60;
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/** @brief `[auth]` section: OAuth 2.0 device authorization grant (RFC 8628) against the company SSO. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthConfig {
+    pub device_authorization_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/** @brief Device authorization response: what the user must enter, and where. */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /** Seconds the device code stays valid. */
+    pub expires_in: u64,
+    /** Seconds between token polls. */
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 { 5 }
+
+impl DeviceCode {
+    /** @brief Page to open for the user: the pre-filled URL when the server offers one. */
+    pub fn browser_url(&self) -> &str {
+        self.verification_uri_complete.as_deref().unwrap_or(&self.verification_uri)
+    }
+}
+
+/** @brief Access token (plus refresh token) as stored between runs. */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub access_token: SecretKey,
+    #[serde(default)]
+    pub refresh_token: Option<SecretKey>,
+    /** Expiry as seconds since the Unix epoch; `None` when the server gave no lifetime. */
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl Token {
+    /**
+     * @brief Whether the token expires within `REFRESH_MARGIN_SECS` of `now`.
+     * @param now Seconds since the Unix epoch.
+     */
+    pub fn needs_refresh(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now + REFRESH_MARGIN_SECS)
+    }
+}
+
+/** Token endpoint answer, successful or not. */
+#[derive(Deserialize)]
+struct TokenReply {
+    access_token: Option<SecretKey>,
+    refresh_token: Option<SecretKey>,
+    expires_in: Option<u64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/** @brief Trust settings for the authorization server: the enrollment CA bundle, without the enrollment pin. */
+pub fn tls_settings(cfg: &crate::config::ClientConfig) -> Result<TlsSettings, VpnError> {
+    TlsSettings::from_config(cfg.enroll_ca_file.as_deref(), None)
+}
+
+/** @brief Token cache for an interface, e.g. `/run/vpn-client/wg-client.token.json`. */
+pub fn token_path(ifname: &str) -> PathBuf {
+    crate::state::runtime_dir().join(format!("{ifname}.token.json"))
+}
+
+/** @brief Stored token for an interface, if any. */
+pub fn load_token(ifname: &str) -> Option<Token> {
+    serde_json::from_str(&fs::read_to_string(token_path(ifname)).ok()?).ok()
+}
+
+/**
+ * @brief Store a token readable only by the current user.
+ * @param ifname Interface the token belongs to.
+ * @param token Token to write.
+ */
+pub fn save_token(ifname: &str, token: &Token) -> Result<(), VpnError> {
+    let path = token_path(ifname);
+    if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+    let json = serde_json::to_string_pretty(token).map_err(|e| VpnError::Auth(e.to_string()))?;
+    keys::write_private_file(&path, json.as_bytes())
+}
+
+/**
+ * @brief Start the device flow.
+ * @param cfg Authorization server settings.
+ * @param tls Trust settings for the authorization server.
+ */
+pub fn request_device_code(cfg: &AuthConfig, tls: &TlsSettings) -> Result<DeviceCode, VpnError> {
+    let mut form = vec![("client_id", cfg.client_id.as_str())];
+    if let Some(scope) = &cfg.scope { form.push(("scope", scope)); }
+    let resp = post_form(&cfg.device_authorization_url, &form, tls)?;
+    if resp.status != 200 {
+        return Err(VpnError::Auth(format!("device authorization failed: {}", oauth_error(&resp))));
+    }
+    let code: DeviceCode = serde_json::from_slice(&resp.body)
+        .map_err(|e| VpnError::Auth(format!("malformed device authorization response: {e}")))?;
+    // shown to the user and opened in the browser
+    for uri in std::iter::once(&code.verification_uri).chain(&code.verification_uri_complete) {
+        browser::check_url(uri).map_err(|e| VpnError::Auth(format!("unusable verification URI: {e}")))?;
+    }
+    Ok(code)
+}
+
+/**
+ * @brief Poll the token endpoint until the user approves, denies or the code expires.
+ * @param cfg Authorization server settings.
+ * @param code Device code from `request_device_code`.
+ * @param tls Trust settings for the authorization server.
+ */
+pub fn poll_token(cfg: &AuthConfig, code: &DeviceCode, tls: &TlsSettings) -> Result<Token, VpnError> {
+    let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+    let mut interval = Duration::from_secs(code.interval);
+    let form = [("grant_type", DEVICE_CODE_GRANT), ("device_code", code.device_code.as_str()), ("client_id", cfg.client_id.as_str())];
+    loop {
+        if Instant::now() >= deadline {
+            return Err(VpnError::Auth("the login code expired before it was approved".into()));
+        }
+        thread::sleep(interval);
+        let resp = post_form(&cfg.token_url, &form, tls)?;
+        let reply = parse_token_reply(&resp)?;
+        match reply.error.as_deref() {
+            None => return token_from(reply, None),
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += Duration::from_secs(5),
+            Some("access_denied") => return Err(VpnError::Auth("login was denied".into())),
+            Some("expired_token") => return Err(VpnError::Auth("the login code expired before it was approved".into())),
+            Some(_) => return Err(VpnError::Auth(format!("token request failed: {}", oauth_error(&resp)))),
+        }
+    }
+}
+
+/**
+ * @brief Exchange a refresh token for a new access token.
+ * @param cfg Authorization server settings.
+ * @param refresh_token Refresh token from an earlier login; kept if the server does not rotate it.
+ * @param tls Trust settings for the authorization server.
+ */
+pub fn refresh(cfg: &AuthConfig, refresh_token: &str, tls: &TlsSettings) -> Result<Token, VpnError> {
+    let form = [("grant_type", "refresh_token"), ("refresh_token", refresh_token), ("client_id", cfg.client_id.as_str())];
+    let resp = post_form(&cfg.token_url, &form, tls)?;
+    let reply = parse_token_reply(&resp)?;
+    if reply.error.is_some() {
+        return Err(VpnError::Auth(format!("session expired ({}); run `vpn-client login`", oauth_error(&resp))));
+    }
+    token_from(reply, Some(refresh_token))
+}
+
+/**
+ * @brief Run the whole device flow and store the resulting token.
+ * @param cfg Authorization server settings.
+ * @param ifname Interface whose token cache is written.
+ * @param tls Trust settings for the authorization server.
+ * @param show Called once with the code so the caller can show it and open the browser.
+ */
+pub fn login(cfg: &AuthConfig, ifname: &str, tls: &TlsSettings, show: &dyn Fn(&DeviceCode)) -> Result<Token, VpnError> {
+    let code = request_device_code(cfg, tls)?;
+    show(&code);
+    let token = poll_token(cfg, &code, tls)?;
+    save_token(ifname, &token)?;
+    Ok(token)
+}
+
+/**
+ * @brief Stored token for `ifname`, refreshed first when it is about to expire.
+ * @return `None` when no token is stored or it expired without a refresh token.
+ */
+pub fn current_token(cfg: &AuthConfig, ifname: &str, tls: &TlsSettings) -> Result<Option<Token>, VpnError> {
+    let Some(token) = load_token(ifname) else { return Ok(None) };
+    if !token.needs_refresh(unix_now()) { return Ok(Some(token)); }
+    let Some(rt) = &token.refresh_token else { return Ok(None) };
+    let fresh = refresh(cfg, rt.expose(), tls)?;
+    save_token(ifname, &fresh)?;
+    Ok(Some(fresh))
+}
+
+fn post_form(url: &str, form: &[(&str, &str)], tls: &TlsSettings) -> Result<HttpResponse, VpnError> {
+    let target = http::parse_url(url).map_err(|e| VpnError::Auth(e.to_string()))?;
+    let body = form.iter().map(|(k, v)| format!("{}={}", form_encode(k), form_encode(v))).collect::<Vec<_>>().join("&");
+    let headers = [("Content-Type", "application/x-www-form-urlencoded"), ("Accept", "application/json")];
+    http::request("POST", &target, &headers, body.as_bytes(), Timeouts::uniform(HTTP_TIMEOUT), tls)
+        .map_err(|e| VpnError::Auth(format!("{target}: {e}")))
+}
+
+fn parse_token_reply(resp: &HttpResponse) -> Result<TokenReply, VpnError> {
+    serde_json::from_slice(&resp.body)
+        .map_err(|_| VpnError::Auth(format!("token endpoint answered {} without a JSON body", resp.status)))
+}
+
+fn token_from(reply: TokenReply, previous_refresh: Option<&str>) -> Result<Token, VpnError> {
+    let access_token = reply.access_token.ok_or_else(|| VpnError::Auth("token response has no access_token".into()))?;
+    Ok(Token {
+        access_token,
+        refresh_token: reply.refresh_token.or(previous_refresh.map(SecretKey::from)),
+        expires_at: reply.expires_in.map(|s| unix_now() + s),
+    })
+}
+
+/** `error: error_description` from an OAuth error body, or the bare status. */
+fn oauth_error(resp: &HttpResponse) -> String {
+    match serde_json::from_slice::<TokenReply>(&resp.body) {
+        Ok(TokenReply { error: Some(e), error_description: Some(d), .. }) => format!("{e}: {d}"),
+        Ok(TokenReply { error: Some(e), .. }) => e,
+        _ => format!("HTTP {}", resp.status),
+    }
+}
+
+/** Percent-encode a form value (RFC 3986 unreserved characters pass through). */
+fn form_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{b:02X}"),
+    }).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,49 @@
+use crate::error::VpnError;
+
+/**
+ * @brief Check that a URL may be handed to the platform's URL opener.
+ * @param target URL, often from an untrusted source (a portal redirect, an SSO server).
+ * @return `VpnError::Connectivity` unless it is an `http://`/`https://` URL per `http::parse_url`
+ *         without whitespace, quotes or control characters.
+ */
+pub fn check_url(target: &str) -> Result<(), VpnError> {
+    crate::http::parse_url(target)?;
+    if target.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '`')) {
+        return Err(VpnError::Connectivity(format!("{}: unexpected characters in URL", target.escape_debug())));
+    }
+    Ok(())
+}
+
+/**
+ * @brief Open a URL in the user's default browser (best-effort, errors ignored).
+ *
+ * URLs failing `check_url` are never opened. As root (e.g. `connect` under sudo, the daemon)
+ * no browser is started; the URL is printed for the user to open instead.
+ * @param target URL to open.
+ */
+pub fn open_url(target: &str) {
+    if let Err(e) = check_url(target) {
+        eprintln!("warning: not opening {e}");
+        return;
+    }
+    if running_as_root() {
+        println!("Open {target} in your browser to continue");
+        return;
+    }
+    // The URL is always a separate argument, never part of a shell or PowerShell command line.
+    if cfg!(target_os = "windows") {
+        let _ = std::process::Command::new("rundll32").args(["url.dll,FileProtocolHandler", target]).output();
+    } else if cfg!(target_os = "macos") {
+        let _ = std::process::Command::new("open").arg(target).output();
+    } else {
+        let _ = std::process::Command::new("xdg-open").arg(target).output();
+    }
+}
+
+/** Whether the effective user is root (never on Windows). */
+fn running_as_root() -> bool {
+    !cfg!(target_os = "windows")
+        && std::process::Command::new("id").arg("-u").output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "0")
+            .unwrap_or(false)
+}
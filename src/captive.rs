@@ -0,0 +1,55 @@
+use crate::http::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+/** @brief `[captive_portal]` section: pre-flight check run before anything is changed on the host. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptivePortal {
+    /** Plain `http://` URL with a known answer, e.g. `http://connectivitycheck.gstatic.com/generate_204`; unset disables the check. */
+    #[serde(default)]
+    pub check_url: Option<String>,
+    #[serde(default = "default_expect_status")]
+    pub expect_status: u16,
+    /** Exact body the check URL returns on an open network (compared after trimming whitespace). */
+    #[serde(default)]
+    pub expect_body: Option<String>,
+    /** How long to wait for the user to log in to the portal. */
+    #[serde(default = "default_login_timeout_secs")]
+    pub login_timeout_secs: u64,
+}
+
+fn default_expect_status() -> u16 { 204 }
+fn default_login_timeout_secs() -> u64 { 300 }
+
+impl Default for CaptivePortal {
+    fn default() -> Self {
+        Self { check_url: None, expect_status: default_expect_status(), expect_body: None, login_timeout_secs: default_login_timeout_secs() }
+    }
+}
+
+/** @brief Outcome of one captive portal check. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortalCheck {
+    /** The check URL answered as expected: the network is open. */
+    Open,
+    /** The answer was intercepted; `login_url` is the redirect target or the check URL itself. */
+    Portal { login_url: String },
+}
+
+/**
+ * @brief Decide from the check URL's response whether a captive portal is intercepting traffic.
+ * @param cfg Captive portal settings (check URL and expected answer).
+ * @param resp Response received for `cfg.check_url`.
+ */
+pub fn classify(cfg: &CaptivePortal, resp: &HttpResponse) -> PortalCheck {
+    let check_url = cfg.check_url.clone().unwrap_or_default();
+    if (300..400).contains(&resp.status) {
+        let login_url = resp.header("location").map(str::to_string).unwrap_or(check_url);
+        return PortalCheck::Portal { login_url };
+    }
+    let body_ok = cfg.expect_body.as_ref()
+        .is_none_or(|b| String::from_utf8_lossy(&resp.body).trim() == b.trim());
+    if resp.status != cfg.expect_status || !body_ok {
+        return PortalCheck::Portal { login_url: check_url };
+    }
+    PortalCheck::Open
+}
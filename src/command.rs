@@ -0,0 +1,20 @@
+use crate::error::VpnError;
+use std::process::Command;
+
+/**
+ * @brief Run an external command and fail unless it exits successfully.
+ * @param program Executable name.
+ * @param args Arguments.
+ * @return Captured stdout.
+ */
+pub(crate) fn run(program: &str, args: &[&str]) -> Result<String, VpnError> {
+    let out = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| VpnError::SystemCommand(format!("{program}: {e}")))?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Err(VpnError::SystemCommand(format!("{program} {}: {}", args.join(" "), stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
@@ -1,6 +1,14 @@
 use serde::{Deserialize, Serialize};
 use base64::Engine as _;
-use std::{fs, path::PathBuf};
+use std::{fs, path::{Path, PathBuf}};
+use crate::error::VpnError;
+use crate::auth::AuthConfig;
+use crate::captive::CaptivePortal;
+use crate::enroll::PendingUnenroll;
+use crate::health::HealthCheck;
+use crate::keys::{self, SecretKey};
+use zeroize::Zeroizing;
+use crate::validate::{unknown_key_warnings, validate_config, ConfigIssue};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
@@ -11,11 +19,80 @@ pub struct ClientConfig {
     pub keepalive_secs: u16,
     pub split_tunnel: bool,
     pub kill_switch: bool,
-    pub client_private_key_b64: Option<String>,
+    /** Base64 client private key; wiped from memory on drop and redacted in `Debug`. */
+    pub client_private_key_b64: Option<SecretKey>,
+    /**
+     * File holding the base64 client private key instead of `client_private_key_b64`; written with
+     * mode 0600. Relative paths are resolved against the config file's directory.
+     */
+    #[serde(default)]
+    pub private_key_file: Option<String>,
+    /** Encrypted content of `private_key_file`, until `keys::unlock_key` decrypts it. */
+    #[serde(skip)]
+    pub sealed_key: Option<keys::SealedKey>,
+    /** Passphrase the key file is encrypted with; `None` keeps it in clear text. Never written to the config. */
+    #[serde(skip)]
+    pub key_passphrase: Option<SecretKey>,
+    /** When the client key was generated (RFC 3339); set by `init`, `enroll`, `connect` and `rotate-key`. */
+    #[serde(default)]
+    pub client_key_created: Option<String>,
+    /** Key age in days after which `status` asks for a rotation; 0 disables the warning. */
+    #[serde(default = "default_key_rotation_days")]
+    pub key_rotation_days: u32,
     pub enroll_url: Option<String>,
     pub welcome_url: Option<String>,
+    /** PEM CA bundle trusted for an `https://` enroll_url instead of the built-in roots. */
+    #[serde(default)]
+    pub enroll_ca_file: Option<String>,
+    /** `sha256/<base64>` pin of the enrollment server's public key, checked on top of the CA chain. */
+    #[serde(default)]
+    pub enroll_spki_pin: Option<String>,
+    /** Bearer token sent with enrollment; `$VPN_CLIENT_ENROLL_TOKEN` takes precedence. */
+    #[serde(default)]
+    pub enroll_token: Option<SecretKey>,
+    /** Where `unenroll` sends the DELETE; unset means `enroll_url`. */
+    #[serde(default)]
+    pub unenroll_url: Option<String>,
+    /** Deregistrations recorded while the server was unreachable, retried by `enroll`, `connect` and `unenroll`. */
+    #[serde(default)]
+    pub pending_unenroll: Vec<PendingUnenroll>,
+    #[serde(default)]
+    pub tunnel_domains: Vec<String>,
+    /** Extra `host:port` endpoints tried in turn when the server stops answering. */
+    #[serde(default)]
+    pub fallback_endpoints: Vec<String>,
+    /** Networks routed through the tunnel; empty means the VPN subnet (split) or everything (full). */
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /** Resolvers set on the interface in full-tunnel mode; empty means the public defaults. */
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
+    #[serde(default)]
+    pub health_check: HealthCheck,
+    #[serde(default)]
+    pub captive_portal: CaptivePortal,
+    /** SSO login (OAuth device flow) whose token authorizes enrollment; unset disables it. */
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /** How long to wait for the first handshake before giving up (or retrying). */
+    #[serde(default = "default_handshake_timeout_secs")]
+    pub handshake_timeout_secs: u64,
+    /** How often to check for the handshake while waiting. */
+    #[serde(default = "default_handshake_poll_ms")]
+    pub handshake_poll_ms: u64,
+    /** Extra handshake attempts after the first times out. */
+    #[serde(default)]
+    pub handshake_retries: u32,
+    /** Pause before the first retry; doubles with each further retry. */
+    #[serde(default = "default_retry_backoff_secs")]
+    pub retry_backoff_secs: u64,
 }
 
+fn default_handshake_timeout_secs() -> u64 { 20 }
+fn default_handshake_poll_ms() -> u64 { 1000 }
+fn default_retry_backoff_secs() -> u64 { 5 }
+fn default_key_rotation_days() -> u32 { crate::keys::DEFAULT_ROTATION_DAYS }
+
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
@@ -27,29 +104,146 @@ impl Default for ClientConfig {
             split_tunnel: false,
             kill_switch: false,
             client_private_key_b64: None,
+            private_key_file: None,
+            sealed_key: None,
+            key_passphrase: None,
+            client_key_created: None,
+            key_rotation_days: default_key_rotation_days(),
             enroll_url: Some("http://127.0.0.1:8080/enroll".into()),
             welcome_url: Some("http://127.0.0.1:8080/".into()),
+            enroll_ca_file: None,
+            enroll_spki_pin: None,
+            enroll_token: None,
+            unenroll_url: None,
+            pending_unenroll: Vec::new(),
+            tunnel_domains: Vec::new(),
+            fallback_endpoints: Vec::new(),
+            allowed_ips: Vec::new(),
+            dns_servers: Vec::new(),
+            health_check: HealthCheck::default(),
+            captive_portal: CaptivePortal::default(),
+            auth: None,
+            handshake_timeout_secs: default_handshake_timeout_secs(),
+            handshake_poll_ms: default_handshake_poll_ms(),
+            handshake_retries: 0,
+            retry_backoff_secs: default_retry_backoff_secs(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /** @brief Networks routed through the tunnel, falling back to the split/full tunnel default. */
+    pub fn effective_allowed_ips(&self) -> Vec<String> {
+        if !self.allowed_ips.is_empty() { return self.allowed_ips.clone(); }
+        vec![if self.split_tunnel { "10.8.0.0/24" } else { "0.0.0.0/0" }.to_string()]
+    }
+
+    /** @brief Deregistration endpoint: `unenroll_url`, or the enrollment URL itself when unset. */
+    pub fn effective_unenroll_url(&self) -> Option<&str> {
+        self.unenroll_url.as_deref().or(self.enroll_url.as_deref())
+    }
+
+    /**
+     * @brief Drop the client key, e.g. once it is deregistered; the next `save_client_config` also
+     * deletes the `private_key_file`.
+     */
+    pub fn forget_key(&mut self) {
+        self.client_private_key_b64 = None;
+        self.sealed_key = None;
+        self.key_passphrase = None;
+        self.client_key_created = None;
+    }
+
+    /** @brief Resolvers applied in full-tunnel mode, falling back to `dns::DEFAULT_SERVERS`. */
+    pub fn effective_dns_servers(&self) -> Vec<String> {
+        if !self.dns_servers.is_empty() { return self.dns_servers.clone(); }
+        crate::dns::DEFAULT_SERVERS.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+/** @brief Result of parsing and validating a config file without acting on it. */
+#[derive(Debug)]
+pub struct ConfigReport {
+    pub config: ClientConfig,
+    pub issues: Vec<ConfigIssue>,
+    pub warnings: Vec<String>,
+}
+
+/**
+ * @brief Parse and validate an existing config file, collecting every problem.
+ *
+ * When `private_key_file` is set, a clear-text key is read from that file into `client_private_key_b64`;
+ * an encrypted one is left in `sealed_key` for `keys::unlock_key`.
+ * @param path Config file to check.
+ * @return Report with the parsed config, validation issues and unknown-key warnings.
+ */
+pub fn check_config_file(path: &Path) -> Result<ConfigReport, VpnError> {
+    let s = Zeroizing::new(fs::read_to_string(path)?);
+    let raw: toml::Value = toml::from_str(&s)?;
+    let warnings = unknown_key_warnings(&raw);
+    let mut config: ClientConfig = toml::from_str(&s)?;
+    let mut issues = validate_config(&config);
+    if let Some(file) = config.private_key_file.clone() {
+        if config.client_private_key_b64.is_some() {
+            issues.push(ConfigIssue { field: "client_private_key_b64".into(), message: "must not be set together with `private_key_file`".into() });
+        }
+        match keys::read_private_key_file(&key_file_path(path, &file)) {
+            Ok(Some(keys::StoredKey::Plain(key))) => config.client_private_key_b64 = Some(key),
+            Ok(Some(keys::StoredKey::Sealed(sealed))) => config.sealed_key = Some(sealed),
+            Ok(None) => {}
+            Err(e) => issues.push(ConfigIssue { field: "private_key_file".into(), message: e.to_string() }),
         }
     }
+    Ok(ConfigReport { config, issues, warnings })
 }
 
 /**
  * @brief Load client configuration from `client.toml`, creating defaults when missing.
+ *
+ * Unknown keys are ignored; use `load_client_config_with` to hear about them. Any validation
+ * issue is returned as `VpnError::InvalidConfig`.
+ */
+pub fn load_client_config(path: Option<PathBuf>) -> Result<ClientConfig, VpnError> {
+    load_client_config_with(path, &|_| {})
+}
+
+/**
+ * @brief `load_client_config`, passing each warning (e.g. an unknown key) to `warn`.
+ * @param path Config file; `None` for the default `client.toml`.
+ * @param warn Called with each warning, prefixed with the file name.
  */
-pub fn load_client_config(path: Option<PathBuf>) -> Result<ClientConfig, Box<dyn std::error::Error>> {
+pub fn load_client_config_with(path: Option<PathBuf>, warn: &dyn Fn(&str)) -> Result<ClientConfig, VpnError> {
     let p = path.unwrap_or_else(|| PathBuf::from("client.toml"));
     if !p.exists() {
         let def = ClientConfig::default();
-        let s = toml::to_string_pretty(&def)?;
-        fs::write(&p, s)?;
+        save_client_config(&def, Some(p))?;
         return Ok(def);
     }
-    let s = fs::read_to_string(p)?;
-    let cfg: ClientConfig = toml::from_str(&s)?;
-    Ok(cfg)
+    let report = check_config_file(&p)?;
+    for w in &report.warnings {
+        warn(&format!("{}: {w}", p.display()));
+    }
+    if !report.issues.is_empty() {
+        return Err(VpnError::InvalidConfig(report.issues));
+    }
+    Ok(report.config)
+}
+
+/**
+ * @brief `load_client_config_with` for commands that use the private key.
+ *
+ * Refuses a config or key file that group or others can access before the key is read.
+ * @param path Config file; `None` for the default `client.toml`.
+ * @param warn Called with each warning, prefixed with the file name.
+ */
+pub fn load_checked_config(path: Option<PathBuf>, warn: &dyn Fn(&str)) -> Result<ClientConfig, VpnError> {
+    let p = path.unwrap_or_else(|| PathBuf::from("client.toml"));
+    if p.exists() { check_private_files(&p)?; }
+    load_client_config_with(Some(p), warn)
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
     #[test]
@@ -63,19 +257,145 @@ mod tests {
 }
 
 /**
- * @brief Ensure client private key exists; if not, generate and persist into `client.toml`.
+ * @brief Ensure client private key exists; if not, generate and persist it (in `client.toml` or `private_key_file`).
  */
-pub fn ensure_client_keys(mut cfg: ClientConfig, path: Option<PathBuf>) -> Result<ClientConfig, Box<dyn std::error::Error>> {
+pub fn ensure_client_keys(mut cfg: ClientConfig, path: Option<PathBuf>) -> Result<ClientConfig, VpnError> {
     if cfg.client_private_key_b64.is_some() {
         return Ok(cfg);
     }
-    let secret = x25519_dalek::StaticSecret::random_from_rng(rand::rngs::OsRng);
-    let public = x25519_dalek::PublicKey::from(&secret);
-    let priv_b64 = base64::engine::general_purpose::STANDARD.encode(secret.to_bytes());
-    cfg.client_private_key_b64 = Some(priv_b64);
+    if cfg.sealed_key.is_some() {
+        return Err(keys::locked_key_error());
+    }
+    cfg.client_private_key_b64 = Some(keys::generate_private_key());
+    cfg.client_key_created = Some(keys::created_now());
+    save_client_config(&cfg, path)?;
+    Ok(cfg)
+}
+
+/**
+ * @brief Write the config back to `client.toml` (or `path`) with mode 0600.
+ *
+ * With `private_key_file` set, the key goes to that file (also 0600) and is left out of the config;
+ * it is encrypted there when `key_passphrase` is set. A still-locked key file is left untouched,
+ * and the file is deleted when the config has no key at all.
+ * @param cfg Config to persist.
+ * @param path Config file; `None` for the default `client.toml`.
+ */
+pub fn save_client_config(cfg: &ClientConfig, path: Option<PathBuf>) -> Result<(), VpnError> {
+    let p = path.unwrap_or_else(|| PathBuf::from("client.toml"));
+    let mut on_disk = cfg.clone();
+    if let Some(file) = &cfg.private_key_file {
+        if let Some(key) = on_disk.client_private_key_b64.take() {
+            let content = Zeroizing::new(match &cfg.key_passphrase {
+                Some(pass) => serde_json::to_string_pretty(&keys::SealedKey::seal(key.expose(), pass.expose())?)
+                    .map_err(|e| VpnError::Passphrase(e.to_string()))?,
+                None => key.expose().to_string(),
+            } + "\n");
+            keys::write_private_file(&key_file_path(&p, file), content.as_bytes())?;
+        } else if cfg.sealed_key.is_none() {
+            // no key at all (e.g. after `unenroll`): a key left in the file would be loaded again
+            match fs::remove_file(key_file_path(&p, file)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+    }
+    keys::write_private_file(&p, Zeroizing::new(toml::to_string_pretty(&on_disk)?).as_bytes())
+}
+
+/**
+ * @brief Refuse to start when the config file or the key file can be read by group or others.
+ * @param cfg Loaded config (for `private_key_file`).
+ * @param path Config file; `None` for the default `client.toml`.
+ */
+pub fn check_file_permissions(cfg: &ClientConfig, path: Option<&Path>) -> Result<(), VpnError> {
+    let p = path.unwrap_or(Path::new("client.toml"));
+    keys::check_private_file(p)?;
+    if let Some(file) = &cfg.private_key_file {
+        keys::check_private_file(&key_file_path(p, file))?;
+    }
+    Ok(())
+}
+
+/** `check_file_permissions` straight from the file, before `check_config_file` reads the key. */
+fn check_private_files(path: &Path) -> Result<(), VpnError> {
+    keys::check_private_file(path)?;
+    let raw: toml::Value = toml::from_str(&Zeroizing::new(fs::read_to_string(path)?))?;
+    if let Some(file) = raw.get("private_key_file").and_then(toml::Value::as_str) {
+        let key_path = key_file_path(path, file);
+        if key_path.exists() { keys::check_private_file(&key_path)?; }
+    }
+    Ok(())
+}
+
+/**
+ * @brief Move an inline `client_private_key_b64` into its own key file and point the config at it.
+ * @param path Config file; `None` for the default `client.toml`.
+ * @param key_file Key file to create, relative to the config's directory; defaults to the config name with `.key`.
+ * @return The key file written, or `None` when the config holds no inline key.
+ */
+pub fn migrate_private_key(path: Option<PathBuf>, key_file: Option<String>) -> Result<Option<PathBuf>, VpnError> {
     let p = path.unwrap_or_else(|| PathBuf::from("client.toml"));
-    let s = toml::to_string_pretty(&cfg)?;
-    fs::write(p, s)?;
-    println!("Client public key: {}", base64::engine::general_purpose::STANDARD.encode(public.as_bytes()));
+    check_private_files(&p)?;
+    let report = check_config_file(&p)?;
+    if !report.issues.is_empty() {
+        return Err(VpnError::InvalidConfig(report.issues));
+    }
+    let mut cfg = report.config;
+    if cfg.private_key_file.is_some() || cfg.client_private_key_b64.is_none() {
+        return Ok(None);
+    }
+    let file = key_file.unwrap_or_else(|| p.with_extension("key").file_name().unwrap_or_default().to_string_lossy().into_owned());
+    let target = key_file_path(&p, &file);
+    if target.exists() {
+        return Err(VpnError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists, format!("{} already exists; not overwriting it", target.display())
+        )));
+    }
+    cfg.private_key_file = Some(file);
+    save_client_config(&cfg, Some(p))?;
+    Ok(Some(target))
+}
+
+/**
+ * @brief Encrypt, re-encrypt or decrypt the key file of an unlocked config.
+ * @param cfg Config with `private_key_file` set and the key unlocked.
+ * @param path Config file; `None` for the default `client.toml`.
+ * @param passphrase New passphrase; `None` stores the key in clear text.
+ */
+pub fn set_key_passphrase(mut cfg: ClientConfig, path: Option<PathBuf>, passphrase: Option<SecretKey>) -> Result<ClientConfig, VpnError> {
+    if cfg.private_key_file.is_none() {
+        return Err(VpnError::Passphrase("only a `private_key_file` can be encrypted; run `vpn-client key migrate` first".into()));
+    }
+    if cfg.client_private_key_b64.is_none() {
+        return Err(keys::locked_key_error());
+    }
+    if passphrase.as_ref().is_some_and(|p| p.expose().is_empty()) {
+        return Err(VpnError::Passphrase("the passphrase must not be empty".into()));
+    }
+    cfg.key_passphrase = passphrase;
+    save_client_config(&cfg, path)?;
     Ok(cfg)
 }
+
+/** Key file location: absolute as given, otherwise next to the config file. */
+fn key_file_path(config_path: &Path, file: &str) -> PathBuf {
+    match config_path.parent() {
+        Some(dir) if Path::new(file).is_relative() => dir.join(file),
+        _ => PathBuf::from(file),
+    }
+}
+
+/**
+ * @brief Derive the base64 public key matching a base64 client private key.
+ * @param private_b64 Client private key as stored in the config.
+ */
+pub fn derive_public_key_b64(private_b64: &str) -> Result<String, VpnError> {
+    let sk_bytes = base64::engine::general_purpose::STANDARD.decode(private_b64).map(Zeroizing::new)
+        .map_err(|e| VpnError::InvalidKey(format!("client_private_key_b64: {e}")))?;
+    let arr = <[u8; 32]>::try_from(sk_bytes.as_slice()).map(Zeroizing::new)
+        .map_err(|_| VpnError::InvalidKey("Client private key must decode to exactly 32 bytes".into()))?;
+    let secret = x25519_dalek::StaticSecret::from(*arr);
+    let public = x25519_dalek::PublicKey::from(&secret);
+    Ok(base64::engine::general_purpose::STANDARD.encode(public.as_bytes()))
+}
@@ -0,0 +1,471 @@
+use crate::error::VpnError;
+use crate::tunnel::{TunnelEvent, TunnelStatus};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+pub use server::{group_id, Daemon, DaemonClient};
+
+/** @brief Default control socket of the daemon. */
+pub const DEFAULT_SOCKET: &str = "/run/vpn-client.sock";
+
+/** @brief Control socket path: `$VPN_CLIENT_SOCKET` when set, otherwise `DEFAULT_SOCKET`. */
+pub fn socket_path() -> PathBuf {
+    std::env::var_os("VPN_CLIENT_SOCKET").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET))
+}
+
+/**
+ * @brief One request line sent to the daemon, e.g. `{"cmd":"up","ifname":"wg-client"}`.
+ *
+ * `ifname` defaults to the interface in the daemon's config.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Request {
+    Up {
+        #[serde(default)]
+        ifname: Option<String>,
+        /** Clean up an orphaned interface that no state file describes. */
+        #[serde(default)]
+        force: bool,
+    },
+    Down {
+        #[serde(default)]
+        ifname: Option<String>,
+    },
+    Status {
+        #[serde(default)]
+        ifname: Option<String>,
+    },
+    /** Re-read the config file and restart running tunnels with it. */
+    Reload,
+    /** Re-read the config file after `rotate-key` and switch running tunnels to the new key in place. */
+    SwapKey,
+    /** Keep the connection open and stream every tunnel state change. */
+    SubscribeEvents,
+}
+
+/** @brief One reply line from the daemon. */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Ok { message: String },
+    Status { status: TunnelStatus, owner_pid: Option<u32> },
+    Event { ifname: String, event: TunnelEvent },
+    Error { code: u8, message: String },
+}
+
+impl Reply {
+    /** @brief Reply describing a failure, keeping the exit code of the error class. */
+    pub fn from_error(e: &VpnError) -> Reply {
+        Reply::Error { code: e.exit_code(), message: e.to_string() }
+    }
+
+    /** @brief Turn an `Error` reply back into a `VpnError::Daemon`. */
+    pub fn into_result(self) -> Result<Reply, VpnError> {
+        match self {
+            Reply::Error { code, message } => Err(VpnError::Daemon { code, message }),
+            other => Ok(other),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod server {
+    use super::{Reply, Request};
+    use crate::config::{ensure_client_keys, load_checked_config, ClientConfig};
+    use crate::error::VpnError;
+    use crate::keys;
+    use crate::lock::{self, InstanceLock};
+    use crate::state;
+    use crate::system::{HostSystem, System};
+    use crate::tunnel::{self, Tunnel, TunnelEvent, TunnelState};
+    use crate::validate::{check_interface_name, ConfigIssue};
+    use crate::filelog::LogSink;
+    use std::{
+        collections::HashMap,
+        fs,
+        io::{BufRead, BufReader, ErrorKind, Write},
+        os::unix::fs::PermissionsExt,
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicBool, Ordering},
+        sync::mpsc::{self, Sender},
+        sync::{Arc, Mutex},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    type Subscribers = Arc<Mutex<Vec<Sender<(String, TunnelEvent)>>>>;
+    /** Config to switch a running tunnel to, and where to report the outcome. */
+    type KeySwap = (ClientConfig, Sender<Result<(), VpnError>>);
+
+    /** A tunnel owned by the daemon, running on its own thread. */
+    struct Session {
+        stop: Arc<AtomicBool>,
+        state: Arc<Mutex<TunnelState>>,
+        swap: Sender<KeySwap>,
+        worker: JoinHandle<()>,
+    }
+
+    /**
+     * @brief Long-lived service that owns tunnels and serves the control socket.
+     *
+     * Each tunnel runs on a worker thread holding the interface's `InstanceLock`, brings the
+     * tunnel up, polls it while connected and tears it down when asked or on shutdown.
+     */
+    pub struct Daemon {
+        cfg_path: Option<PathBuf>,
+        cfg: Mutex<ClientConfig>,
+        sys: Arc<dyn System>,
+        host: bool,
+        sessions: Mutex<HashMap<String, Session>>,
+        subscribers: Subscribers,
+        stop: Arc<AtomicBool>,
+        log: Mutex<LogSink>,
+    }
+
+    impl Daemon {
+        /**
+         * @brief Daemon driving the real host, reloading its config from `cfg_path`.
+         * @param cfg Loaded configuration with a client key.
+         * @param cfg_path Config file to re-read on `reload`; `None` for the default `client.toml`.
+         */
+        pub fn new(cfg: ClientConfig, cfg_path: Option<PathBuf>) -> Arc<Daemon> {
+            Self::build(cfg, cfg_path, Arc::new(HostSystem), true)
+        }
+
+        /**
+         * @brief Daemon applying changes through `sys` without writing runtime state files.
+         * @param cfg Loaded configuration with a client key.
+         * @param cfg_path Config file to re-read on `reload`.
+         * @param sys System layer (the real host or a test double).
+         */
+        pub fn with_system(cfg: ClientConfig, cfg_path: Option<PathBuf>, sys: Arc<dyn System>) -> Arc<Daemon> {
+            Self::build(cfg, cfg_path, sys, false)
+        }
+
+        fn build(cfg: ClientConfig, cfg_path: Option<PathBuf>, sys: Arc<dyn System>, host: bool) -> Arc<Daemon> {
+            Arc::new(Daemon {
+                cfg_path,
+                cfg: Mutex::new(cfg),
+                sys,
+                host,
+                sessions: Mutex::new(HashMap::new()),
+                subscribers: Arc::new(Mutex::new(Vec::new())),
+                stop: Arc::new(AtomicBool::new(false)),
+                log: Mutex::new(LogSink::default()),
+            })
+        }
+
+        /** @brief Log the daemon's and its tunnels' progress to `log`; applies to tunnels started afterwards. */
+        pub fn log_to(&self, log: LogSink) {
+            *self.log.lock().unwrap() = log;
+        }
+
+        fn log_line(&self, line: &str) {
+            self.log.lock().unwrap().line(line);
+        }
+
+        /** @brief Flag that stops `serve` and tears every tunnel down, typically set from a signal handler. */
+        pub fn stop_handle(&self) -> Arc<AtomicBool> { self.stop.clone() }
+
+        /**
+         * @brief Listen on `path` until stopped.
+         *
+         * The socket is created with mode 0660 and, when `group` is given, handed to that
+         * group so its members can control the VPN without root.
+         * @param path Socket path, usually `socket_path()`.
+         * @param group Group name or numeric gid owning the socket.
+         */
+        pub fn serve(self: &Arc<Self>, path: &Path, group: Option<&str>) -> Result<(), VpnError> {
+            let listener = bind(path, group)?;
+            listener.set_nonblocking(true)?;
+            self.log_line(&format!("Daemon listening on {}", path.display()));
+            while !self.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let this = self.clone();
+                        thread::spawn(move || this.handle_client(stream));
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(200)),
+                    Err(e) => self.log_line(&format!("Daemon accept failed: {e}")),
+                }
+            }
+            self.shutdown();
+            let _ = fs::remove_file(path);
+            Ok(())
+        }
+
+        /** @brief Tear down every tunnel the daemon owns. */
+        pub fn shutdown(&self) {
+            let names: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+            for name in names {
+                self.stop_session(&name);
+            }
+        }
+
+        /**
+         * @brief Execute one request.
+         * @param req Parsed request; `SubscribeEvents` is only meaningful on a connection and is acknowledged here.
+         * @return Reply to send back.
+         */
+        pub fn handle(&self, req: Request) -> Reply {
+            let result = match req {
+                Request::Up { ifname, force } => self.up(ifname, force),
+                Request::Down { ifname } => self.down(ifname),
+                Request::Status { ifname } => self.status(ifname),
+                Request::Reload => self.reload(),
+                Request::SwapKey => self.swap_key(),
+                Request::SubscribeEvents => Ok(Reply::Ok { message: "subscribed".into() }),
+            };
+            result.unwrap_or_else(|e| Reply::from_error(&e))
+        }
+
+        fn handle_client(&self, stream: UnixStream) {
+            let Ok(read_half) = stream.try_clone() else { return };
+            let mut out = stream;
+            for line in BufReader::new(read_half).lines() {
+                let Ok(line) = line else { return };
+                if line.trim().is_empty() { continue; }
+                let reply = match serde_json::from_str::<Request>(&line) {
+                    Ok(Request::SubscribeEvents) => {
+                        let _ = send(&mut out, &self.handle(Request::SubscribeEvents));
+                        self.stream_events(out);
+                        return;
+                    }
+                    Ok(req) => self.handle(req),
+                    Err(e) => Reply::Error { code: 2, message: format!("bad request: {e}") },
+                };
+                if send(&mut out, &reply).is_err() { return; }
+            }
+        }
+
+        fn stream_events(&self, mut out: UnixStream) {
+            let (tx, rx) = mpsc::channel();
+            self.subscribers.lock().unwrap().push(tx);
+            for (ifname, event) in rx {
+                if send(&mut out, &Reply::Event { ifname, event }).is_err() { return; }
+            }
+        }
+
+        /** Interface a request is about; a client-supplied name must pass the `interface_name` rules. */
+        fn ifname(&self, ifname: Option<String>) -> Result<String, VpnError> {
+            let Some(name) = ifname else { return Ok(self.cfg.lock().unwrap().interface_name.clone()) };
+            check_interface_name(&name)
+                .map_err(|message| VpnError::InvalidConfig(vec![ConfigIssue { field: "ifname".into(), message }]))?;
+            Ok(name)
+        }
+
+        fn up(&self, ifname: Option<String>, force: bool) -> Result<Reply, VpnError> {
+            let cfg = self.cfg.lock().unwrap().clone();
+            let name = self.ifname(ifname)?;
+            self.start_session(cfg, &name, force)?;
+            Ok(Reply::Ok { message: format!("{name} connected") })
+        }
+
+        fn down(&self, ifname: Option<String>) -> Result<Reply, VpnError> {
+            let name = self.ifname(ifname)?;
+            if self.stop_session(&name) {
+                Ok(Reply::Ok { message: format!("{name} disconnected") })
+            } else {
+                Ok(Reply::Ok { message: format!("{name} is not connected") })
+            }
+        }
+
+        fn status(&self, ifname: Option<String>) -> Result<Reply, VpnError> {
+            let name = self.ifname(ifname)?;
+            let mut status = if self.host {
+                tunnel::read_status(&name)
+            } else {
+                tunnel::status_from_system(self.sys.as_ref(), &name)
+            };
+            if let Some(s) = self.sessions.lock().unwrap().get(&name) {
+                status.state = *s.state.lock().unwrap();
+            }
+            Ok(Reply::Status { status, owner_pid: lock::lock_owner(&name) })
+        }
+
+        fn reload(&self) -> Result<Reply, VpnError> {
+            let cfg = ensure_client_keys(self.load_config()?, self.cfg_path.clone())?;
+            *self.cfg.lock().unwrap() = cfg.clone();
+            let running: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+            for name in &running {
+                self.stop_session(name);
+                self.start_session(cfg.clone(), name, false)?;
+            }
+            Ok(Reply::Ok { message: format!("config reloaded, {} tunnel(s) restarted", running.len()) })
+        }
+
+        /**
+         * Re-read the config file, refusing files others can read, and unlock an encrypted key with
+         * the passphrase the daemon started with.
+         */
+        fn load_config(&self) -> Result<ClientConfig, VpnError> {
+            let mut cfg = load_checked_config(self.cfg_path.clone(), &|w| self.log_line(&format!("Warning: {w}")))?;
+            let passphrase = self.cfg.lock().unwrap().key_passphrase.clone();
+            keys::unlock_key(&mut cfg, &|| passphrase.clone().ok_or_else(keys::locked_key_error))?;
+            Ok(cfg)
+        }
+
+        fn swap_key(&self) -> Result<Reply, VpnError> {
+            let cfg = self.load_config()?;
+            *self.cfg.lock().unwrap() = cfg.clone();
+            let pending: Vec<_> = self.sessions.lock().unwrap().iter()
+                .filter(|(_, s)| !s.worker.is_finished())
+                .filter_map(|(name, s)| {
+                    let (tx, rx) = mpsc::channel();
+                    s.swap.send((cfg.clone(), tx)).ok().map(|_| (name.clone(), rx))
+                })
+                .collect();
+            for (name, rx) in &pending {
+                rx.recv().unwrap_or_else(|_| Err(VpnError::SystemCommand(format!("tunnel worker for {name} exited"))))?;
+            }
+            Ok(Reply::Ok { message: format!("key swapped on {} tunnel(s)", pending.len()) })
+        }
+
+        /** Lock the interface, bring the tunnel up on a worker thread and wait for the outcome. */
+        fn start_session(&self, cfg: ClientConfig, name: &str, force: bool) -> Result<(), VpnError> {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.get(name).is_some_and(|s| !s.worker.is_finished()) {
+                return Err(VpnError::AlreadyRunning { ifname: name.to_string(), pid: Some(std::process::id()) });
+            }
+            let instance_lock = InstanceLock::acquire(name)?;
+            for action in state::clear_leftovers(self.sys.as_ref(), name, force, cfg.kill_switch)? {
+                self.log_line(&format!("Cleanup on {name}: {action}"));
+            }
+            let mut tunnel = if self.host {
+                Tunnel::new(cfg, Some(name.to_string()))
+            } else {
+                Tunnel::with_system(cfg, Some(name.to_string()), self.sys.clone())
+            };
+            let log = self.log.lock().unwrap().clone();
+            tunnel.log_to(log.clone());
+            let stop = tunnel.stop_handle();
+            let state = Arc::new(Mutex::new(tunnel.state()));
+            forward_events(name, tunnel.subscribe(), state.clone(), self.subscribers.clone(), log.clone());
+
+            let (done_tx, done_rx) = mpsc::channel();
+            let (swap, swap_rx) = mpsc::channel::<KeySwap>();
+            let worker = thread::spawn(move || {
+                let _lock = instance_lock;
+                let res = tunnel.up();
+                let ok = res.is_ok();
+                let _ = done_tx.send(res);
+                if !ok { return; }
+                if let Err(e) = tunnel.watch_network() {
+                    log.line(&format!("Not following network changes: {e}"));
+                }
+                while !tunnel.stop_requested() {
+                    for _ in 0..5 {
+                        if tunnel.stop_requested() { break; }
+                        while let Ok((cfg, reply)) = swap_rx.try_recv() {
+                            let _ = reply.send(tunnel.swap_key(cfg));
+                        }
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                    tunnel.poll();
+                }
+                let _ = tunnel.down();
+            });
+            sessions.insert(name.to_string(), Session { stop, state, swap, worker });
+            drop(sessions);
+            done_rx.recv().unwrap_or(Err(VpnError::SystemCommand(format!("tunnel worker for {name} exited"))))
+        }
+
+        /** Stop a session and wait for its teardown; false when none was running. */
+        fn stop_session(&self, name: &str) -> bool {
+            let Some(session) = self.sessions.lock().unwrap().remove(name) else { return false };
+            session.stop.store(true, Ordering::SeqCst);
+            let running = !session.worker.is_finished();
+            let _ = session.worker.join();
+            running
+        }
+    }
+
+    /** Track a session's state and fan its events out to the log and socket subscribers. */
+    fn forward_events(name: &str, events: mpsc::Receiver<TunnelEvent>, state: Arc<Mutex<TunnelState>>, subscribers: Subscribers, log: LogSink) {
+        let name = name.to_string();
+        thread::spawn(move || {
+            for ev in events {
+                *state.lock().unwrap() = ev.to;
+                log.line(&format!("State {name}: {} -> {} {}", ev.from, ev.to, ev.detail));
+                subscribers.lock().unwrap().retain(|s| s.send((name.clone(), ev.clone())).is_ok());
+            }
+        });
+    }
+
+    fn send(out: &mut UnixStream, reply: &Reply) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(reply).map_err(std::io::Error::other)?;
+        line.push('\n');
+        out.write_all(line.as_bytes())
+    }
+
+    fn bind(path: &Path, group: Option<&str>) -> Result<UnixListener, VpnError> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(VpnError::SystemCommand(format!("a daemon is already listening on {}", path.display())));
+        }
+        let _ = fs::remove_file(path);
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+        if let Some(group) = group {
+            let gid = group_id(group).ok_or_else(|| VpnError::SystemCommand(format!("unknown group `{group}`")))?;
+            std::os::unix::fs::chown(path, None, Some(gid))?;
+        }
+        Ok(listener)
+    }
+
+    /**
+     * @brief Resolve a group name (or numeric gid) through `/etc/group`.
+     * @param group Group name or gid.
+     */
+    pub fn group_id(group: &str) -> Option<u32> {
+        if let Ok(gid) = group.parse() { return Some(gid); }
+        let groups = fs::read_to_string("/etc/group").ok()?;
+        groups.lines().find_map(|l| {
+            let mut f = l.split(':');
+            (f.next()? == group).then_some(())?;
+            f.nth(1)?.parse().ok()
+        })
+    }
+
+    /** @brief Line-delimited JSON connection to a running daemon. */
+    pub struct DaemonClient {
+        out: UnixStream,
+        input: BufReader<UnixStream>,
+    }
+
+    impl DaemonClient {
+        /**
+         * @brief Connect to the daemon's control socket.
+         * @param path Socket path, usually `socket_path()`.
+         * @return Error when no daemon is listening.
+         */
+        pub fn connect(path: &Path) -> Result<DaemonClient, VpnError> {
+            let out = UnixStream::connect(path)?;
+            let input = BufReader::new(out.try_clone()?);
+            Ok(DaemonClient { out, input })
+        }
+
+        /**
+         * @brief Send a request and wait for its reply.
+         * @return The reply; `Error` replies become `VpnError::Daemon`.
+         */
+        pub fn request(&mut self, req: &Request) -> Result<Reply, VpnError> {
+            let mut line = serde_json::to_string(req).map_err(|e| VpnError::ConfigParse(e.to_string()))?;
+            line.push('\n');
+            self.out.write_all(line.as_bytes())?;
+            self.next_reply()?
+                .ok_or_else(|| VpnError::SystemCommand("daemon closed the connection".into()))?
+                .into_result()
+        }
+
+        /** @brief Next reply line, e.g. an event after `SubscribeEvents`; `None` once the daemon hangs up. */
+        pub fn next_reply(&mut self) -> Result<Option<Reply>, VpnError> {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 { return Ok(None); }
+            serde_json::from_str(&line).map(Some).map_err(|e| VpnError::ConfigParse(format!("bad daemon reply: {e}")))
+        }
+    }
+}
@@ -1,69 +1,87 @@
-#[cfg(target_os = "linux")]
-use std::process::Command;
+#[cfg(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows"))]
+use crate::command::run;
+use crate::error::VpnError;
+use serde::{Deserialize, Serialize};
 
-#[allow(dead_code)]
-#[derive(Clone, Debug)]
+/** @brief Resolver state captured before the tunnel changes it. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DnsSnapshot {
+    pub ifname: String,
     pub resolv_conf: Option<String>,
 }
 
 /**
- * @brief Capture current DNS resolver configuration (Linux).
- * @return Snapshot of `/etc/resolv.conf` content.
+ * @brief Capture current DNS resolver configuration.
+ * @param ifname Interface the tunnel DNS will be applied to.
+ * @return Snapshot of `/etc/resolv.conf` content (Linux); interface name only elsewhere.
  */
-#[cfg(target_os = "linux")]
-pub fn snapshot_dns() -> DnsSnapshot {
-    let content = std::fs::read_to_string("/etc/resolv.conf").ok();
-    DnsSnapshot { resolv_conf: content }
+pub fn snapshot_dns(ifname: &str) -> DnsSnapshot {
+    let resolv_conf = if cfg!(target_os = "linux") { std::fs::read_to_string("/etc/resolv.conf").ok() } else { None };
+    DnsSnapshot { ifname: ifname.to_string(), resolv_conf }
 }
 
+/** @brief Resolvers used in full-tunnel mode when neither the config nor enrollment names any. */
+pub const DEFAULT_SERVERS: &[&str] = &["1.1.1.1", "8.8.8.8"];
+
 /**
  * @brief Apply full-tunnel DNS settings using systemd-resolved (Linux).
  * @param ifname Interface alias.
+ * @param servers Resolver addresses to set on the interface.
  */
-#[cfg(target_os = "linux")]
-pub fn apply_full_tunnel_dns(ifname: &str) {
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn apply_full_tunnel_dns(ifname: &str, servers: &[String]) -> Result<(), VpnError> {
     // Prefer systemd-resolved
-    let _ = Command::new("resolvectl").args(["dns", ifname, "1.1.1.1", "8.8.8.8"]).output();
-    let _ = Command::new("resolvectl").args(["domain", ifname, "~."]).output();
+    let mut args = vec!["dns", ifname];
+    args.extend(servers.iter().map(String::as_str));
+    run("resolvectl", &args)?;
+    run("resolvectl", &["domain", ifname, "~."])?;
+    Ok(())
 }
 
 /**
  * @brief Restore DNS configuration from snapshot (Linux).
  * @param snapshot Previously captured DNS settings.
  */
-#[cfg(target_os = "linux")]
-pub fn restore_dns(snapshot: &DnsSnapshot) {
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn restore_dns(snapshot: &DnsSnapshot) -> Result<(), VpnError> {
+    let _ = run("resolvectl", &["revert", &snapshot.ifname]);
     if let Some(ref content) = snapshot.resolv_conf {
-        let _ = std::fs::write("/etc/resolv.conf", content);
+        std::fs::write("/etc/resolv.conf", content)?;
     }
+    Ok(())
 }
 
-/** @brief Capture DNS configuration (Windows stub). */
-#[cfg(target_os = "windows")]
-pub fn snapshot_dns() -> DnsSnapshot { DnsSnapshot { resolv_conf: None } }
-
 /**
  * @brief Apply DNS servers on the interface (Windows best-effort).
  * @param ifname Interface alias.
+ * @param servers Resolver addresses to set on the interface.
  */
 #[cfg(target_os = "windows")]
-pub fn apply_full_tunnel_dns(ifname: &str) {
+pub fn apply_full_tunnel_dns(ifname: &str, servers: &[String]) -> Result<(), VpnError> {
     // Set DNS servers on the interface; best-effort
-    let _ = std::process::Command::new("powershell").args([
+    let list: Vec<String> = servers.iter().map(|s| format!("'{s}'")).collect();
+    run("powershell", &[
         "-Command",
-        &format!("Set-DnsClientServerAddress -InterfaceAlias '{}' -ServerAddresses @('1.1.1.1','8.8.8.8')", ifname),
-    ]).output();
+        &format!("Set-DnsClientServerAddress -InterfaceAlias '{}' -ServerAddresses @({})", ifname, list.join(",")),
+    ]).map(|_| ())
 }
 
 /**
  * @brief Restore DNS settings to default (Windows).
- * @param ifname Interface alias.
+ * @param snapshot Snapshot naming the interface to reset.
  */
 #[cfg(target_os = "windows")]
-pub fn restore_dns(ifname: &str) {
-    let _ = std::process::Command::new("powershell").args([
+pub fn restore_dns(snapshot: &DnsSnapshot) -> Result<(), VpnError> {
+    run("powershell", &[
         "-Command",
-        &format!("Set-DnsClientServerAddress -InterfaceAlias '{}' -ResetServerAddresses", ifname),
-    ]).output();
+        &format!("Set-DnsClientServerAddress -InterfaceAlias '{}' -ResetServerAddresses", snapshot.ifname),
+    ]).map(|_| ())
 }
+
+/** @brief Apply tunnel DNS (stub without platform support). */
+#[cfg(not(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows")))]
+pub fn apply_full_tunnel_dns(_ifname: &str, _servers: &[String]) -> Result<(), VpnError> { Ok(()) }
+
+/** @brief Restore DNS (stub without platform support). */
+#[cfg(not(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows")))]
+pub fn restore_dns(_snapshot: &DnsSnapshot) -> Result<(), VpnError> { Ok(()) }
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, net::IpAddr, path::{Path, PathBuf}};
+use crate::error::VpnError;
+
+/** @brief Lower bound on how long a resolved address stays routed, to avoid churn on tiny TTLs. */
+pub const MIN_TTL_SECS: u64 = 30;
+
+/** @brief One resolved address of a tunnelled domain and when it stops being routed. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainRoute {
+    pub domain: String,
+    pub addr: IpAddr,
+    /** Unix time (seconds) at which the entry expires. */
+    pub expires_at: u64,
+}
+
+/** @brief Addresses added to and removed from the tunnel by a refresh. */
+#[derive(Debug, Default, PartialEq)]
+pub struct DomainDelta {
+    pub added: Vec<IpAddr>,
+    pub removed: Vec<IpAddr>,
+}
+
+impl DomainDelta {
+    pub fn is_empty(&self) -> bool { self.added.is_empty() && self.removed.is_empty() }
+}
+
+/** @brief Current mapping of `tunnel_domains` to the addresses routed through the tunnel. */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DomainTable {
+    #[serde(default)]
+    pub entries: Vec<DomainRoute>,
+}
+
+impl DomainTable {
+    /**
+     * @brief Re-resolve domains whose entries are missing or expired and drop stale addresses.
+     * @param domains Configured `tunnel_domains`.
+     * @param now Current Unix time in seconds.
+     * @param resolve Resolver returning `(address, ttl_secs)` answers for a name.
+     * @return Addresses that must be added to or removed from the peer and route table.
+     */
+    pub fn refresh<F>(&mut self, domains: &[String], now: u64, mut resolve: F) -> DomainDelta
+    where
+        F: FnMut(&str) -> Vec<(IpAddr, u32)>,
+    {
+        let before = self.addresses();
+        for domain in domains {
+            let mut current = self.entries.iter().filter(|e| &e.domain == domain).peekable();
+            let needs_resolve = current.peek().is_none() || current.any(|e| e.expires_at <= now);
+            if !needs_resolve { continue; }
+            for (addr, ttl) in resolve(domain) {
+                let expires_at = now + u64::from(ttl).max(MIN_TTL_SECS);
+                match self.entries.iter_mut().find(|e| &e.domain == domain && e.addr == addr) {
+                    Some(e) => e.expires_at = expires_at,
+                    None => self.entries.push(DomainRoute { domain: domain.clone(), addr, expires_at }),
+                }
+            }
+        }
+        self.entries.retain(|e| e.expires_at > now && domains.contains(&e.domain));
+        let after = self.addresses();
+        DomainDelta {
+            added: after.iter().filter(|a| !before.contains(a)).copied().collect(),
+            removed: before.iter().filter(|a| !after.contains(a)).copied().collect(),
+        }
+    }
+
+    /** @brief Distinct addresses currently routed, in sorted order. */
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        let mut out: Vec<IpAddr> = self.entries.iter().map(|e| e.addr).collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    /**
+     * @brief Persist the table so `status` can show it from another process.
+     * @param path Destination file, usually `table_path(ifname)`.
+     */
+    pub fn save(&self, path: &Path) -> Result<(), VpnError> {
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /** @brief Load a previously saved table; missing or unreadable files yield `None`. */
+    pub fn load(path: &Path) -> Option<DomainTable> {
+        let s = fs::read_to_string(path).ok()?;
+        toml::from_str(&s).ok()
+    }
+}
+
+/** @brief File where `connect` publishes the domain mapping for an interface. */
+pub fn table_path(ifname: &str) -> PathBuf {
+    crate::state::runtime_dir().join(format!("{ifname}.domains.toml"))
+}
+
+/** @brief Current Unix time in seconds. */
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,307 @@
+use crate::config::{derive_public_key_b64, ClientConfig};
+use crate::error::VpnError;
+use crate::http::{self, HttpResponse, HttpUrl, Timeouts};
+use crate::keys::{self, KeyProof, SecretKey};
+use crate::tls::TlsSettings;
+use crate::validate::validate_config;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/** @brief Redirects followed before enrollment gives up. */
+pub const MAX_REDIRECTS: usize = 5;
+
+/** @brief Environment variable holding the enrollment token; overrides `enroll_token`. */
+pub const TOKEN_ENV: &str = "VPN_CLIENT_ENROLL_TOKEN";
+
+/** @brief Time limits, redirect budget, TLS trust and credentials for one enrollment. */
+#[derive(Debug, Clone)]
+pub struct EnrollOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub max_redirects: usize,
+    pub tls: TlsSettings,
+    /** Enrollment token or one-time invite code, sent as `Authorization: Bearer`. */
+    pub token: Option<SecretKey>,
+    /** Proof of the previous key, sent when enrolling a rotated key. */
+    pub proof: Option<KeyProof>,
+}
+
+impl Default for EnrollOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(10),
+            max_redirects: MAX_REDIRECTS,
+            tls: TlsSettings::default(),
+            token: None,
+            proof: None,
+        }
+    }
+}
+
+impl EnrollOptions {
+    /**
+     * @brief Default limits with the CA bundle and pin from `enroll_ca_file` / `enroll_spki_pin`.
+     *
+     * The token comes from `$VPN_CLIENT_ENROLL_TOKEN`, falling back to `enroll_token`.
+     */
+    pub fn from_config(cfg: &ClientConfig) -> Result<Self, VpnError> {
+        let tls = TlsSettings::from_config(cfg.enroll_ca_file.as_deref(), cfg.enroll_spki_pin.as_deref())
+            .map_err(as_enrollment)?;
+        let token = std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty()).map(SecretKey::from).or_else(|| cfg.enroll_token.clone());
+        Ok(Self { tls, token, ..Self::default() })
+    }
+}
+
+/**
+ * @brief POST the client public key to the server enrollment endpoint.
+ * @param url Enrollment URL from `enroll_url`.
+ * @param pub_b64 Client public key, sent as a `text/plain` body.
+ * @param opts Timeouts, redirect limit, TLS trust and token.
+ * @return The final 2xx response. 401, 403 and 409 map to `EnrollUnauthorized`, `EnrollForbidden` and
+ *         `KeyAlreadyEnrolled`; other failures map to `VpnError::Enrollment`.
+ */
+pub fn enroll(url: &str, pub_b64: &str, opts: &EnrollOptions) -> Result<HttpResponse, VpnError> {
+    let (target, resp) = send_key("POST", url, pub_b64, opts).map_err(|e| match e {
+        VpnError::Connectivity(m) => VpnError::Enrollment(m),
+        e => e,
+    })?;
+    check_status(&target, resp)
+}
+
+/**
+ * @brief Ask the server to forget a public key by sending it in a DELETE request.
+ * @param url Deregistration URL, usually `ClientConfig::effective_unenroll_url`.
+ * @param pub_b64 Public key to deregister, sent as a `text/plain` body.
+ * @param opts Timeouts, redirect limit, TLS trust and token.
+ * @return `Ok` once the key is gone; 404 and 410 count as already gone. `VpnError::Connectivity` means the
+ *         server could not be reached (or answered 502–504) and the request is worth retrying later.
+ */
+pub fn unenroll(url: &str, pub_b64: &str, opts: &EnrollOptions) -> Result<(), VpnError> {
+    let (target, resp) = send_key("DELETE", url, pub_b64, opts)?;
+    match resp.status {
+        404 | 410 => Ok(()),
+        502..=504 => Err(VpnError::Connectivity(status_error(&target, resp.status, &resp))),
+        _ => check_status(&target, resp).map(|_| ()),
+    }
+}
+
+/** @brief Deregistration that could not reach the server, kept in the config until a retry succeeds. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUnenroll {
+    pub url: String,
+    /** Public key (base64) the server should forget; the private key is already gone. */
+    pub public_key: String,
+}
+
+/**
+ * @brief Retry every deregistration in `cfg.pending_unenroll`.
+ * @param cfg Config whose pending list is updated: entries stay only while the server is unreachable.
+ * @param opts Timeouts, TLS trust and token for the requests.
+ * @return Each attempted entry with its outcome, so the caller can report it.
+ */
+pub fn retry_pending_unenroll(cfg: &mut ClientConfig, opts: &EnrollOptions) -> Vec<(PendingUnenroll, Result<(), VpnError>)> {
+    let mut outcomes = Vec::new();
+    for pending in std::mem::take(&mut cfg.pending_unenroll) {
+        let res = unenroll(&pending.url, &pending.public_key, opts);
+        if matches!(res, Err(VpnError::Connectivity(_))) { cfg.pending_unenroll.push(pending.clone()); }
+        outcomes.push((pending, res));
+    }
+    outcomes
+}
+
+/**
+ * Send `pub_b64` with `method`, following redirects.
+ * @return The URL that finally answered and its (non-redirect) response; transport failures come back as `Connectivity`.
+ */
+fn send_key(method: &'static str, url: &str, pub_b64: &str, opts: &EnrollOptions) -> Result<(HttpUrl, HttpResponse), VpnError> {
+    let mut target = http::parse_url(url).map_err(as_enrollment)?;
+    let origin = (target.https, target.host.clone(), target.port);
+    let bearer = match &opts.token {
+        Some(t) if !is_valid_token(t.expose()) => return Err(VpnError::Enrollment("token must be printable ASCII without spaces".into())),
+        Some(t) => Some(Zeroizing::new(format!("Bearer {}", t.expose()))),
+        None => None,
+    };
+    let timeouts = Timeouts { connect: opts.connect_timeout, read: opts.read_timeout };
+    let mut method = method;
+    for _ in 0..=opts.max_redirects {
+        let body: &[u8] = if method == "GET" { &[] } else { pub_b64.as_bytes() };
+        let mut headers = vec![("Accept", "application/json")];
+        if method != "GET" { headers.push(("Content-Type", "text/plain")); }
+        // credentials only go to the origin they were issued for
+        if (target.https, target.host.clone(), target.port) == origin {
+            if let Some(b) = &bearer { headers.push(("Authorization", b.as_str())); }
+            if let Some(p) = &opts.proof {
+                headers.push((keys::PREVIOUS_KEY_HEADER, &p.previous_key));
+                headers.push((keys::KEY_PROOF_HEADER, &p.proof));
+            }
+        }
+        let resp = http::request(method, &target, &headers, body, timeouts, &opts.tls)
+            .map_err(|e| VpnError::Connectivity(format!("{target}: {}", detail(e))))?;
+        match resp.status {
+            301 | 302 | 303 | 307 | 308 => {
+                let location = resp.header("location")
+                    .ok_or_else(|| VpnError::Enrollment(format!("{target}: {} redirect without Location", resp.status)))?;
+                let next = target.join(location).map_err(as_enrollment)?;
+                refuse_downgrade(&target, &next)?;
+                // 303 asks for a GET of the result; the other codes re-send the request itself
+                if resp.status == 303 { method = "GET"; }
+                target = next;
+            }
+            _ => return Ok((target, resp)),
+        }
+    }
+    Err(VpnError::Enrollment(format!("{url}: more than {} redirects", opts.max_redirects)))
+}
+
+/** Pass a 2xx response through; map 401, 403 and 409 to their own errors and anything else to `Enrollment`. */
+fn check_status(target: &HttpUrl, resp: HttpResponse) -> Result<HttpResponse, VpnError> {
+    match resp.status {
+        200..=299 => Ok(resp),
+        401 => Err(VpnError::EnrollUnauthorized(status_error(target, 401, &resp))),
+        403 => Err(VpnError::EnrollForbidden(status_error(target, 403, &resp))),
+        409 => Err(VpnError::KeyAlreadyEnrolled(status_error(target, 409, &resp))),
+        status => Err(VpnError::Enrollment(status_error(target, status, &resp))),
+    }
+}
+
+/** @brief Settings the server assigns in a JSON enrollment response. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnrollResponse {
+    /** Tunnel address for this client, e.g. `10.8.0.7/32`. */
+    pub address_cidr: String,
+    /** Server WireGuard public key (base64). */
+    pub server_public_key: String,
+    /** `host:port` endpoints; the first is primary, the rest become `fallback_endpoints`. */
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub dns: Vec<String>,
+    #[serde(default)]
+    pub keepalive_secs: Option<u16>,
+}
+
+impl EnrollResponse {
+    /**
+     * @brief Decode the body of a successful enrollment.
+     * @param resp Final 2xx response.
+     * @return `None` when the server sent no JSON (older servers answer with an empty or plain-text body).
+     */
+    pub fn from_response(resp: &HttpResponse) -> Result<Option<Self>, VpnError> {
+        let json = resp.header("content-type").is_some_and(|t| t.to_ascii_lowercase().starts_with("application/json"));
+        let body = String::from_utf8_lossy(&resp.body);
+        if !json && !body.trim_start().starts_with('{') {
+            return Ok(None);
+        }
+        let parsed: Self = serde_json::from_str(&body)
+            .map_err(|e| VpnError::Enrollment(format!("malformed enrollment response: {e}")))?;
+        if parsed.endpoints.is_empty() {
+            return Err(VpnError::Enrollment("enrollment response lists no endpoints".into()));
+        }
+        Ok(Some(parsed))
+    }
+
+    /**
+     * @brief Overwrite the server-assigned fields of `cfg`.
+     * @param cfg Config to update; fields the response leaves empty keep their current value.
+     */
+    pub fn apply(&self, cfg: &mut ClientConfig) {
+        cfg.address_cidr = self.address_cidr.clone();
+        cfg.server_public_key_b64 = self.server_public_key.clone();
+        cfg.server_endpoint = self.endpoints[0].clone();
+        cfg.fallback_endpoints = self.endpoints[1..].to_vec();
+        if !self.allowed_ips.is_empty() { cfg.allowed_ips = self.allowed_ips.clone(); }
+        if !self.dns.is_empty() { cfg.dns_servers = self.dns.clone(); }
+        if let Some(k) = self.keepalive_secs { cfg.keepalive_secs = k; }
+    }
+}
+
+/**
+ * @brief Enroll `cfg`'s public key at its `enroll_url` and merge any settings the server assigns.
+ * @param cfg Config with a client private key and `enroll_url`; updated in place only if the merged result validates.
+ * @param opts Timeouts, redirect limit and TLS trust.
+ * @return Whether the server sent a configuration that was applied.
+ */
+pub fn enroll_and_apply(cfg: &mut ClientConfig, opts: &EnrollOptions) -> Result<bool, VpnError> {
+    let url = cfg.enroll_url.clone().ok_or_else(|| VpnError::Enrollment("enroll_url is not set".into()))?;
+    let private_key = cfg.client_private_key_b64.as_ref()
+        .ok_or_else(|| VpnError::InvalidKey("Missing client private key".into()))?;
+    let resp = enroll(&url, &derive_public_key_b64(private_key.expose())?, opts)?;
+    let Some(assigned) = EnrollResponse::from_response(&resp)? else { return Ok(false) };
+    let mut merged = cfg.clone();
+    assigned.apply(&mut merged);
+    let issues = validate_config(&merged);
+    if !issues.is_empty() {
+        let list: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        return Err(VpnError::Enrollment(format!("server assigned an invalid configuration: {}", list.join("; "))));
+    }
+    *cfg = merged;
+    Ok(true)
+}
+
+/**
+ * @brief Generate a new client key and enroll it, authenticated by the current key (and the token, if any).
+ * @param cfg Current config with a client key, `enroll_url` and the server public key; left untouched.
+ * @param opts Timeouts, TLS trust and token.
+ * @return The config to switch to: new key, its creation date and any settings the server assigned.
+ */
+pub fn rotate_key(cfg: &ClientConfig, opts: &EnrollOptions) -> Result<ClientConfig, VpnError> {
+    let old_key = cfg.client_private_key_b64.as_ref()
+        .ok_or_else(|| VpnError::InvalidKey("Missing client private key".into()))?;
+    let new_key = keys::generate_private_key();
+    let new_public = derive_public_key_b64(new_key.expose())?;
+    let mut rotated = cfg.clone();
+    rotated.client_private_key_b64 = Some(new_key);
+    rotated.client_key_created = Some(keys::created_now());
+    let mut opts = opts.clone();
+    if !cfg.server_public_key_b64.is_empty() {
+        opts.proof = Some(keys::key_proof(old_key.expose(), &cfg.server_public_key_b64, &new_public)?);
+    } else if opts.token.is_none() {
+        return Err(VpnError::Enrollment("cannot authenticate the new key: no server public key for a proof and no token".into()));
+    }
+    enroll_and_apply(&mut rotated, &opts)?;
+    Ok(rotated)
+}
+
+/**
+ * @brief Whether `token` can be sent in an `Authorization` header as is.
+ * @param token Enrollment token or invite code.
+ */
+pub fn is_valid_token(token: &str) -> bool {
+    !token.is_empty() && token.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/** Never follow a redirect from `https://` back to plain `http://`. */
+fn refuse_downgrade(from: &HttpUrl, to: &HttpUrl) -> Result<(), VpnError> {
+    if from.https && !to.https {
+        return Err(VpnError::Enrollment(format!("{from}: refusing redirect to insecure {to}")));
+    }
+    Ok(())
+}
+
+/** Describe a non-2xx, non-redirect answer, including the start of its body. */
+fn status_error(target: &HttpUrl, status: u16, resp: &HttpResponse) -> String {
+    let kind = if status >= 500 { "server error" } else if status >= 400 { "rejected" } else { "unexpected status" };
+    let body = String::from_utf8_lossy(&resp.body);
+    let body = body.trim();
+    if body.is_empty() {
+        format!("{target}: {kind} ({status})")
+    } else {
+        format!("{target}: {kind} ({status}): {}", body.chars().take(200).collect::<String>())
+    }
+}
+
+fn detail(e: VpnError) -> String {
+    match e {
+        VpnError::Connectivity(m) => m,
+        VpnError::Io(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => "timed out".into(),
+        VpnError::Io(e) => e.to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn as_enrollment(e: VpnError) -> VpnError {
+    VpnError::Enrollment(detail(e))
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use crate::validate::ConfigIssue;
+
+/**
+ * @brief Errors returned by the VPN client library.
+ *
+ * Each variant maps to a distinct process exit code via `exit_code`, so wrapper
+ * tooling can tell failure classes apart without parsing messages.
+ */
+#[derive(Debug)]
+pub enum VpnError {
+    /** The configuration file could not be read as TOML or written back. */
+    ConfigParse(String),
+    /** A key is not valid base64 or does not decode to 32 bytes. */
+    InvalidKey(String),
+    /** An address or allowed-IP value is not a valid CIDR. */
+    InvalidCidr(String),
+    /** The server endpoint is not a valid `host:port`. */
+    InvalidEndpoint(String),
+    /** The enrollment request could not be delivered or was rejected. */
+    Enrollment(String),
+    /** No handshake with the server was seen within the timeout. */
+    HandshakeTimeout(String),
+    /** The tunnel came up but traffic could not pass through it. */
+    Connectivity(String),
+    /** Creating or configuring the interface, firewall, routes or DNS failed. */
+    SystemCommand(String),
+    /** Local I/O failure (files, sockets). */
+    Io(std::io::Error),
+    /** The configuration parsed but failed validation; lists every problem found. */
+    InvalidConfig(Vec<ConfigIssue>),
+    /** Setup was cancelled (Ctrl+C) and rolled back. */
+    Interrupted,
+    /** Another process holds the lock for this interface. */
+    AlreadyRunning { ifname: String, pid: Option<u32> },
+    /** The enrollment server wants credentials (HTTP 401): the token is missing, expired or wrong. */
+    EnrollUnauthorized(String),
+    /** The enrollment server refused this client (HTTP 403), e.g. an invite code that was already used. */
+    EnrollForbidden(String),
+    /** The server already has this public key registered (HTTP 409). */
+    KeyAlreadyEnrolled(String),
+    /** Signing in with the identity provider failed, was denied or has expired. */
+    Auth(String),
+    /** The config or key file can be read by other users. */
+    InsecurePermissions(String),
+    /** The encrypted private key could not be unlocked: no passphrase available, or the wrong one. */
+    Passphrase(String),
+    /** The daemon reported a failure; carries the exit code it would have used. */
+    Daemon { code: u8, message: String },
+}
+
+impl VpnError {
+    /** @brief Process exit code for this error class. */
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            VpnError::ConfigParse(_) => 10,
+            VpnError::InvalidKey(_) => 11,
+            VpnError::InvalidCidr(_) => 12,
+            VpnError::InvalidEndpoint(_) => 13,
+            VpnError::Enrollment(_) => 14,
+            VpnError::HandshakeTimeout(_) => 15,
+            VpnError::Connectivity(_) => 16,
+            VpnError::SystemCommand(_) => 17,
+            VpnError::Io(_) => 18,
+            VpnError::InvalidConfig(_) => 19,
+            VpnError::AlreadyRunning { .. } => 20,
+            VpnError::EnrollUnauthorized(_) => 21,
+            VpnError::EnrollForbidden(_) => 22,
+            VpnError::KeyAlreadyEnrolled(_) => 23,
+            VpnError::Auth(_) => 24,
+            VpnError::InsecurePermissions(_) => 25,
+            VpnError::Passphrase(_) => 26,
+            VpnError::Daemon { code, .. } => *code,
+            VpnError::Interrupted => 130,
+        }
+    }
+}
+
+impl fmt::Display for VpnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VpnError::ConfigParse(m) => write!(f, "Config error: {m}"),
+            VpnError::InvalidKey(m) => write!(f, "Invalid key: {m}"),
+            VpnError::InvalidCidr(m) => write!(f, "Invalid CIDR: {m}"),
+            VpnError::InvalidEndpoint(m) => write!(f, "Invalid endpoint: {m}"),
+            VpnError::Enrollment(m) => write!(f, "Enrollment failed: {m}"),
+            VpnError::HandshakeTimeout(m) => write!(f, "Handshake timeout: {m}"),
+            VpnError::Connectivity(m) => write!(f, "Connectivity failed: {m}"),
+            VpnError::SystemCommand(m) => write!(f, "System command failed: {m}"),
+            VpnError::Io(e) => write!(f, "I/O error: {e}"),
+            VpnError::Interrupted => write!(f, "Interrupted — changes rolled back"),
+            VpnError::AlreadyRunning { ifname, pid: Some(pid) } => write!(f, "{ifname} is already in use by process {pid}"),
+            VpnError::Daemon { message, .. } => write!(f, "Daemon: {message}"),
+            VpnError::EnrollUnauthorized(m) => write!(f, "Enrollment needs a valid token (use --token, VPN_CLIENT_ENROLL_TOKEN or enroll_token): {m}"),
+            VpnError::EnrollForbidden(m) => write!(f, "Enrollment refused: {m}"),
+            VpnError::KeyAlreadyEnrolled(m) => write!(f, "This key is already enrolled: {m}"),
+            VpnError::Auth(m) => write!(f, "Login failed: {m}"),
+            VpnError::InsecurePermissions(m) => write!(f, "Refusing to start: {m}"),
+            VpnError::Passphrase(m) => write!(f, "Cannot unlock the private key: {m}"),
+            VpnError::AlreadyRunning { ifname, pid: None } => write!(f, "{ifname} is already in use by another process"),
+            VpnError::InvalidConfig(issues) => {
+                write!(f, "Invalid config ({} problem(s))", issues.len())?;
+                for i in issues { write!(f, "\n  {i}")?; }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for VpnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VpnError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VpnError {
+    fn from(e: std::io::Error) -> Self { VpnError::Io(e) }
+}
+
+impl From<toml::de::Error> for VpnError {
+    fn from(e: toml::de::Error) -> Self { VpnError::ConfigParse(e.to_string()) }
+}
+
+impl From<toml::ser::Error> for VpnError {
+    fn from(e: toml::ser::Error) -> Self { VpnError::ConfigParse(e.to_string()) }
+}
+
+impl From<defguard_wireguard_rs::error::WireguardInterfaceError> for VpnError {
+    fn from(e: defguard_wireguard_rs::error::WireguardInterfaceError) -> Self { VpnError::SystemCommand(e.to_string()) }
+}
@@ -1,16 +1,34 @@
 use std::fs::{OpenOptions};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/** @brief Log file used by the client when no other path is configured. */
+pub const DEFAULT_LOG: &str = "vpn-client.log";
 
 /**
  * @brief Append a single line to a log file, creating the file if missing.
  * @param path Path to the log file.
  * @param line Text line to append.
  */
-pub fn write_line(path: &str, line: &str) {
-    let p = Path::new(path);
-    let f = OpenOptions::new().create(true).append(true).open(p).ok();
+pub fn write_line(path: impl AsRef<Path>, line: &str) {
+    let f = OpenOptions::new().create(true).append(true).open(path.as_ref()).ok();
     if let Some(mut file) = f {
         let _ = writeln!(file, "{}", line);
     }
 }
+
+/** @brief Where a `Tunnel`, its `Journal` or the `Daemon` logs: nowhere until a file is set. */
+#[derive(Debug, Clone, Default)]
+pub struct LogSink(Option<PathBuf>);
+
+impl LogSink {
+    /** @brief Sink appending to `path`, e.g. `DEFAULT_LOG`. */
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        LogSink(Some(path.into()))
+    }
+
+    /** @brief Append `line` when a file is set. */
+    pub fn line(&self, line: &str) {
+        if let Some(path) = &self.0 { write_line(path, line) }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::error::VpnError;
+use crate::system::System;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/** @brief One connectivity probe from `[[health_check.probes]]`. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Probe {
+    /** TCP connect to `host:port`. */
+    Tcp { target: String },
+    /** One ICMP echo via the system `ping`. */
+    Icmp { host: String },
+    /** `GET url` answered with `expect_status`. */
+    Http {
+        url: String,
+        #[serde(default = "default_expect_status")]
+        expect_status: u16,
+    },
+    /**
+     * A query for `name` answered by `server` (default: the tunnel's first DNS server, or the system
+     * resolver when the tunnel sets no DNS).
+     */
+    Dns {
+        name: String,
+        #[serde(default)]
+        server: Option<String>,
+    },
+}
+
+fn default_expect_status() -> u16 { 200 }
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Tcp { target } => write!(f, "tcp {target}"),
+            Probe::Icmp { host } => write!(f, "icmp {host}"),
+            Probe::Http { url, expect_status } => write!(f, "http {url} (expect {expect_status})"),
+            Probe::Dns { name, server: Some(s) } => write!(f, "dns {name} @{s}"),
+            Probe::Dns { name, server: None } => write!(f, "dns {name}"),
+        }
+    }
+}
+
+/** @brief How many probes must pass for the tunnel to count as healthy. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthPolicy {
+    #[default]
+    All,
+    Any,
+}
+
+/** @brief `[health_check]` section: probes run after the handshake and periodically while connected. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /** Probes to run; when empty, full tunnels probe `1.1.1.1:443` and split tunnels rely on the handshake alone. */
+    #[serde(default)]
+    pub probes: Vec<Probe>,
+    #[serde(default)]
+    pub policy: HealthPolicy,
+    /** Timeout for each probe. */
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /** Seconds between checks while connected; 0 checks only once after the handshake. */
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 { 3 }
+fn default_interval_secs() -> u64 { 60 }
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self { probes: Vec::new(), policy: HealthPolicy::All, timeout_secs: default_timeout_secs(), interval_secs: default_interval_secs() }
+    }
+}
+
+impl HealthCheck {
+    /**
+     * @brief Probes that will actually run.
+     * @param split_tunnel Whether only the VPN subnet is routed through the tunnel.
+     */
+    pub fn effective_probes(&self, split_tunnel: bool) -> Vec<Probe> {
+        if self.probes.is_empty() && !split_tunnel {
+            return vec![Probe::Tcp { target: "1.1.1.1:443".into() }];
+        }
+        self.probes.clone()
+    }
+}
+
+/**
+ * @brief Run the configured probes through `sys` and apply the policy.
+ * @param sys System layer that executes each probe.
+ * @param hc Health check settings.
+ * @param split_tunnel Whether the tunnel is split (affects the default probe).
+ * @param tunnel_dns Resolvers the tunnel applied; the first answers `dns` probes without a `server`.
+ * @return `VpnError::Connectivity` listing the failed probes when the policy is not met.
+ */
+pub fn run_health_check(sys: &dyn System, hc: &HealthCheck, split_tunnel: bool, tunnel_dns: &[String]) -> Result<(), VpnError> {
+    let probes: Vec<Probe> = hc.effective_probes(split_tunnel).into_iter()
+        .map(|probe| match probe {
+            Probe::Dns { name, server: None } => Probe::Dns { name, server: tunnel_dns.first().cloned() },
+            other => other,
+        })
+        .collect();
+    if probes.is_empty() { return Ok(()); }
+    let timeout = Duration::from_secs(hc.timeout_secs.max(1));
+    let mut failures = Vec::new();
+    for probe in &probes {
+        match sys.probe_connectivity(probe, timeout) {
+            Ok(()) if hc.policy == HealthPolicy::Any => return Ok(()),
+            Ok(()) => {}
+            Err(e) => failures.push(format!("{probe}: {e}")),
+        }
+    }
+    if failures.is_empty() { return Ok(()); }
+    Err(VpnError::Connectivity(format!("{}/{} probe(s) failed: {}", failures.len(), probes.len(), failures.join("; "))))
+}
+
+/**
+ * @brief Execute one probe against the real network.
+ * @param probe Probe to run.
+ * @param timeout Time limit for the probe.
+ */
+pub fn run_probe(probe: &Probe, timeout: Duration) -> Result<(), VpnError> {
+    use std::net::{SocketAddr, ToSocketAddrs};
+    let fail = VpnError::Connectivity;
+    match probe {
+        Probe::Tcp { target } => {
+            let addr = target.to_socket_addrs()?.next().ok_or_else(|| fail(format!("{target} does not resolve")))?;
+            std::net::TcpStream::connect_timeout(&addr, timeout).map(|_| ()).map_err(|e| fail(e.to_string()))
+        }
+        Probe::Icmp { host } => {
+            let out = if cfg!(target_os = "windows") {
+                let ms = timeout.as_millis().max(1000).to_string();
+                crate::command::run("ping", &["-n", "1", "-w", &ms, host])
+            } else {
+                let secs = timeout.as_secs().max(1).to_string();
+                crate::command::run("ping", &["-c", "1", "-W", &secs, host])
+            };
+            out.map(|_| ()).map_err(|e| fail(e.to_string()))
+        }
+        Probe::Http { url, expect_status } => match crate::http::get_status(url, timeout)? {
+            code if code == *expect_status => Ok(()),
+            code => Err(fail(format!("status {code}, expected {expect_status}"))),
+        },
+        Probe::Dns { name, server } => {
+            let ip = match server {
+                Some(s) => s.parse().map_err(|_| fail(format!("bad DNS server `{s}`")))?,
+                None => crate::resolver::system_nameserver().ok_or_else(|| fail("no nameserver configured".into()))?,
+            };
+            let answers = crate::resolver::query_a(SocketAddr::new(ip, 53), name, timeout)?;
+            if answers.is_empty() { Err(fail(format!("no A records for {name}"))) } else { Ok(()) }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::error::VpnError;
+use crate::tls::TlsSettings;
+use rustls::pki_types::ServerName;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/** @brief Largest response body read; anything beyond is dropped. */
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/** @brief Parts of an `http://` or `https://` URL. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub https: bool,
+    /** Host name or address; IPv6 literals are stored without brackets. */
+    pub host: String,
+    pub port: u16,
+    /** Path and query, always starting with `/`. */
+    pub path: String,
+}
+
+impl HttpUrl {
+    /** @brief `host:port` as written in a `Host` header (IPv6 in brackets, default port omitted). */
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == if self.https { 443 } else { 80 } { host } else { format!("{host}:{}", self.port) }
+    }
+
+    /**
+     * @brief Resolve a `Location` header against this URL.
+     * @param location Absolute URL, or an absolute path on the same host.
+     */
+    pub fn join(&self, location: &str) -> Result<HttpUrl, VpnError> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return parse_url(location);
+        }
+        if let Some(rest) = location.strip_prefix("//") {
+            return parse_url(&format!("{}://{rest}", if self.https { "https" } else { "http" }));
+        }
+        if !location.starts_with('/') {
+            return Err(VpnError::Connectivity(format!("unsupported redirect target `{location}`")));
+        }
+        Ok(HttpUrl { path: location.to_string(), ..self.clone() })
+    }
+}
+
+impl std::fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://{}{}", if self.https { "https" } else { "http" }, self.authority(), self.path)
+    }
+}
+
+/** @brief Connect and read/write time limits for one HTTP exchange. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub read: Duration,
+}
+
+impl Timeouts {
+    /** @brief Use the same limit for connecting and reading. */
+    pub fn uniform(timeout: Duration) -> Self { Self { connect: timeout, read: timeout } }
+}
+
+/** @brief Status, headers and (de-chunked) body of an HTTP response. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    /** Header names are lower-cased. */
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /** @brief First value of a header, matched case-insensitively. */
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(k, _)| *k == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/**
+ * @brief Split an `http://` or `https://` URL into scheme, host, port (default 80/443) and path (default `/`).
+ * @param url URL to parse; user info is rejected and any `#fragment` is dropped.
+ */
+pub fn parse_url(url: &str) -> Result<HttpUrl, VpnError> {
+    let bad = |why: &str| VpnError::Connectivity(format!("{url}: {why}"));
+    let (https, rest) = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (false, rest),
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (true, rest),
+        _ => return Err(bad("only http:// and https:// URLs are supported")),
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    if authority.contains('@') { return Err(bad("user info in URLs is not supported")); }
+    let default_port = if https { 443 } else { 80 };
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        let (host, after) = v6.split_once(']').ok_or_else(|| bad("unterminated IPv6 literal"))?;
+        match after.strip_prefix(':') {
+            Some(p) => (host, p.parse().map_err(|_| bad(&format!("bad port `{p}`")))?),
+            None if after.is_empty() => (host, default_port),
+            None => return Err(bad("garbage after IPv6 literal")),
+        }
+    } else {
+        match authority.rsplit_once(':') {
+            Some((h, p)) => (h, p.parse().map_err(|_| bad(&format!("bad port `{p}`")))?),
+            None => (authority, default_port),
+        }
+    };
+    if host.is_empty() { return Err(bad("missing host")); }
+    Ok(HttpUrl { https, host: host.to_string(), port, path })
+}
+
+/**
+ * @brief Split a plain `http://` URL; `https://` is refused.
+ * @param url URL to parse.
+ */
+pub fn parse_http_url(url: &str) -> Result<HttpUrl, VpnError> {
+    let u = parse_url(url)?;
+    if u.https { return Err(VpnError::Connectivity(format!("{url}: only http:// URLs are supported"))); }
+    Ok(u)
+}
+
+/**
+ * @brief Send `GET url` and read the whole response. Redirects are returned, not followed.
+ * @param url Plain `http://` URL.
+ * @param timeout Connect, read and write timeout.
+ */
+pub fn get(url: &str, timeout: Duration) -> Result<HttpResponse, VpnError> {
+    request("GET", &parse_http_url(url)?, &[], &[], Timeouts::uniform(timeout), &TlsSettings::default())
+}
+
+/**
+ * @brief Send one request over a fresh connection and read the whole response. Redirects are not followed.
+ * @param method Request method, e.g. `POST`.
+ * @param url Target; `https://` is verified according to `tls`.
+ * @param headers Extra request headers (`Host`, `Content-Length` and `Connection` are added).
+ * @param body Request body, sent only when non-empty.
+ * @param timeouts Connect and read/write limits.
+ * @param tls Trust roots and pin used for `https://`; certificate failures are errors, never skipped.
+ */
+pub fn request(method: &str, url: &HttpUrl, headers: &[(&str, &str)], body: &[u8], timeouts: Timeouts, tls: &TlsSettings) -> Result<HttpResponse, VpnError> {
+    let tls_conn = if url.https {
+        let name = ServerName::try_from(url.host.clone())
+            .map_err(|_| VpnError::Connectivity(format!("{url}: `{}` is not a valid TLS server name", url.host)))?;
+        let conn = rustls::ClientConnection::new(tls.client_config()?, name)
+            .map_err(|e| VpnError::Connectivity(format!("{url}: TLS: {e}")))?;
+        Some(conn)
+    } else {
+        None
+    };
+    let addr = (url.host.as_str(), url.port).to_socket_addrs()?.next()
+        .ok_or_else(|| VpnError::Connectivity(format!("{url}: host does not resolve")))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeouts.connect)?;
+    stream.set_read_timeout(Some(timeouts.read))?;
+    stream.set_write_timeout(Some(timeouts.read))?;
+    let req = encode_request(method, url, headers, body);
+    let raw = if let Some(conn) = tls_conn {
+        let mut tls_stream = rustls::StreamOwned::new(conn, stream);
+        exchange(&mut tls_stream, &req).map_err(|e| tls_error(url, e))?
+    } else {
+        exchange(&mut stream, &req)?
+    };
+    parse_response(&raw).ok_or_else(|| VpnError::Connectivity(format!("{url}: malformed or truncated HTTP response")))
+}
+
+/** Write the request and read until the server closes the connection. */
+fn exchange(stream: &mut (impl Read + Write), req: &[u8]) -> std::io::Result<Vec<u8>> {
+    stream.write_all(req)?;
+    stream.flush()?;
+    let mut raw = Vec::new();
+    match stream.take((MAX_BODY_BYTES + 16 * 1024) as u64).read_to_end(&mut raw) {
+        // servers often close without a TLS close_notify; the body length is checked by the parser
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof && !raw.is_empty() => Ok(raw),
+        other => other.map(|_| raw),
+    }
+}
+
+/** Surface certificate and handshake failures as TLS errors rather than plain I/O. */
+fn tls_error(url: &HttpUrl, e: std::io::Error) -> VpnError {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+        Some(tls) => VpnError::Connectivity(format!("{url}: TLS: {tls}")),
+        None => VpnError::Io(e),
+    }
+}
+
+fn encode_request(method: &str, url: &HttpUrl, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut head = format!("{method} {} HTTP/1.1\r\nHost: {}\r\n", url.path, url.authority());
+    for (k, v) in headers {
+        head.push_str(&format!("{k}: {v}\r\n"));
+    }
+    if !body.is_empty() || method == "POST" || method == "PUT" {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    let mut out = head.into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+/**
+ * @brief Send `GET url` and return the response status code.
+ * @param url Plain `http://` URL.
+ * @param timeout Connect, read and write timeout.
+ */
+pub fn get_status(url: &str, timeout: Duration) -> Result<u16, VpnError> {
+    get(url, timeout).map(|r| r.status)
+}
+
+/**
+ * @brief Parse a raw HTTP/1.x response, decoding a chunked body.
+ * @param raw Bytes read from the connection.
+ * @return `None` when the status line or headers are malformed, or the body is shorter than its `Content-Length`.
+ */
+pub fn parse_response(raw: &[u8]) -> Option<HttpResponse> {
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..split]).ok()?;
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next()?;
+    if !status_line.starts_with("HTTP/") { return None; }
+    let status = status_line.split_whitespace().nth(1)?.parse().ok()?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    let mut resp = HttpResponse { status, headers, body: Vec::new() };
+    let body = &raw[split + 4..];
+    let chunked = resp.header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+    resp.body = if chunked { dechunk(body)? } else { body.to_vec() };
+    if let Some(len) = resp.header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+        // cut short by the peer; a body over MAX_BODY_BYTES was cut by `exchange` and is capped below
+        if resp.body.len() < len.min(MAX_BODY_BYTES) { return None; }
+        resp.body.truncate(len);
+    }
+    resp.body.truncate(MAX_BODY_BYTES);
+    Some(resp)
+}
+
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let eol = body.windows(2).position(|w| w == b"\r\n")?;
+        let size_field = std::str::from_utf8(&body[..eol]).ok()?;
+        let size = usize::from_str_radix(size_field.split(';').next()?.trim(), 16).ok()?;
+        body = &body[eol + 2..];
+        if size == 0 { return Some(out); }
+        if body.len() < size { return None; }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
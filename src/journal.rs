@@ -0,0 +1,141 @@
+use crate::dns::DnsSnapshot;
+use crate::error::VpnError;
+use crate::filelog::LogSink;
+use crate::state::RuntimeState;
+use crate::system::System;
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr, path::PathBuf, sync::Arc};
+
+/** @brief A host change applied while bringing a tunnel up, with enough data to undo it. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    Interface { ifname: String },
+    KillSwitch { ifname: String },
+    HostRoute { endpoint_ip: String },
+    Dns { snapshot: DnsSnapshot },
+    TunnelRoute { addr: IpAddr, ifname: String },
+}
+
+/** Describes the undo step, e.g. `removed interface wg-client`, as `recover` reports it. */
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Interface { ifname } => write!(f, "removed interface {ifname}"),
+            Change::KillSwitch { ifname } => write!(f, "reverted kill switch for {ifname}"),
+            Change::HostRoute { endpoint_ip } => write!(f, "removed host route to {endpoint_ip}"),
+            Change::Dns { snapshot } => write!(f, "restored DNS for {}", snapshot.ifname),
+            Change::TunnelRoute { addr, ifname } => write!(f, "removed route to {addr} via {ifname}"),
+        }
+    }
+}
+
+/**
+ * @brief Ordered record of applied changes that undoes them in reverse.
+ *
+ * The journal unwinds itself when dropped, so an early return or a panic during setup
+ * still restores the network. Call `unwind` explicitly for an orderly teardown.
+ * When a state file is attached, every change is mirrored to it so another process
+ * (`disconnect`, `recover`) can undo them too.
+ */
+pub struct Journal {
+    sys: Arc<dyn System>,
+    changes: Vec<Change>,
+    failed: Vec<Change>,
+    state_file: Option<(String, PathBuf)>,
+    log: LogSink,
+}
+
+impl Journal {
+    /** @brief Empty journal undoing through `sys`. */
+    pub fn new(sys: Arc<dyn System>) -> Self {
+        Self { sys, changes: Vec::new(), failed: Vec::new(), state_file: None, log: LogSink::default() }
+    }
+
+    /**
+     * @brief Mirror the journal to a runtime state file from now on.
+     * @param ifname Interface the changes belong to.
+     * @param path State file, usually `state::state_path(ifname)`.
+     */
+    pub fn persist_to(&mut self, ifname: &str, path: PathBuf) {
+        self.state_file = Some((ifname.to_string(), path));
+    }
+
+    /** @brief Log state file and rollback failures to `log` from now on. */
+    pub fn log_to(&mut self, log: LogSink) {
+        self.log = log;
+    }
+
+    /** @brief Remember a change so it is undone on unwind. */
+    pub fn record(&mut self, change: Change) {
+        self.changes.push(change);
+        self.sync();
+    }
+
+    /** @brief Drop a change that was undone individually (e.g. an expired tunnel route). */
+    pub fn forget(&mut self, change: &Change) {
+        if let Some(pos) = self.changes.iter().rposition(|c| c == change) {
+            self.changes.remove(pos);
+        }
+        self.sync();
+    }
+
+    /** @brief Changes currently recorded, oldest first. */
+    pub fn changes(&self) -> &[Change] { &self.changes }
+
+    /** @brief True when nothing needs undoing. */
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+    /**
+     * @brief Undo every recorded change, newest first.
+     * @return Errors from undo steps that failed; the remaining steps still run. Failed
+     *         changes stay in the state file so `recover` can retry them.
+     */
+    pub fn unwind(&mut self) -> Vec<VpnError> {
+        let mut errors = Vec::new();
+        while let Some(change) = self.changes.pop() {
+            if let Err(e) = undo(self.sys.as_ref(), &change) {
+                errors.push(e);
+                self.failed.insert(0, change);
+            }
+            self.sync();
+        }
+        errors
+    }
+
+    fn sync(&self) {
+        let Some((ifname, path)) = &self.state_file else { return };
+        let changes: Vec<Change> = self.failed.iter().chain(&self.changes).cloned().collect();
+        if changes.is_empty() {
+            let _ = std::fs::remove_file(path);
+            return;
+        }
+        let state = RuntimeState { ifname: ifname.clone(), pid: std::process::id(), changes };
+        if let Err(e) = state.save(path) {
+            self.log.line(&format!("Could not write runtime state {}: {e}", path.display()));
+        }
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        for e in self.unwind() {
+            self.log.line(&format!("Rollback: {e}"));
+        }
+    }
+}
+
+/**
+ * @brief Revert a single change.
+ * @param sys System layer to act on.
+ * @param change Change to revert.
+ */
+pub fn undo(sys: &dyn System, change: &Change) -> Result<(), VpnError> {
+    match change {
+        Change::Interface { ifname } => sys.remove_interface(ifname),
+        Change::KillSwitch { ifname } => sys.revert_kill_switch(ifname),
+        Change::HostRoute { endpoint_ip } => sys.remove_host_route(endpoint_ip),
+        Change::Dns { snapshot } => sys.restore_dns(snapshot),
+        Change::TunnelRoute { addr, ifname } => sys.del_tunnel_route(addr, ifname),
+    }
+}
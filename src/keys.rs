@@ -0,0 +1,378 @@
+use crate::config::ClientConfig;
+use crate::error::VpnError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine as _;
+use chacha20poly1305::{aead::{Aead, Payload}, KeyInit as _, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use subtle::ConstantTimeEq as _;
+use std::{fmt, fs, io::Write, path::Path};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+/** @brief Default for `key_rotation_days`: the security policy's rotation period. */
+pub const DEFAULT_ROTATION_DAYS: u32 = 90;
+
+/** @brief Header carrying the public key being replaced during a rotation. */
+pub const PREVIOUS_KEY_HEADER: &str = "X-Previous-Key";
+
+/** @brief Header carrying the proof that the client still holds the previous private key. */
+pub const KEY_PROOF_HEADER: &str = "X-Key-Proof";
+
+const PROOF_LABEL: &[u8] = b"vpn-client key rotation v1\n";
+
+/** @brief Environment variable holding the key passphrase, for the daemon and scripts. */
+pub const PASSPHRASE_ENV: &str = "VPN_CLIENT_KEY_PASSPHRASE";
+
+/** @brief Environment variable naming a file with the key passphrase (e.g. a systemd credential). */
+pub const PASSPHRASE_FILE_ENV: &str = "VPN_CLIENT_KEY_PASSPHRASE_FILE";
+
+/** @brief Current version of the encrypted key file format. */
+pub const SEALED_KEY_VERSION: u32 = 1;
+
+const SEALED_KEY_AAD: &[u8] = b"vpn-client sealed key v1";
+
+/** @brief Most Argon2 memory (KiB, 1 GiB) a key file may ask for. */
+pub const MAX_KDF_M_COST: u32 = 1024 * 1024;
+/** @brief Most Argon2 iterations a key file may ask for. */
+pub const MAX_KDF_T_COST: u32 = 10;
+/** @brief Most Argon2 lanes a key file may ask for. */
+pub const MAX_KDF_P_COST: u32 = 8;
+
+/** @brief Proof sent with a rotation that the new key comes from the holder of the old one. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyProof {
+    /** Public key (base64) being replaced. */
+    pub previous_key: String,
+    /** Base64 HMAC-SHA256 over the new public key, see `key_proof`. */
+    pub proof: String,
+}
+
+/**
+ * @brief Secret text that is wiped from memory when dropped: the base64 client private key, the
+ * passphrase its key file is encrypted with, or an enrollment or SSO token.
+ *
+ * `Debug` prints `SecretKey(<redacted>)`, so a config can be logged; `expose` hands the value to
+ * the code that actually needs it. Compare with `matches`, which takes constant time.
+ */
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(transparent)]
+pub struct SecretKey(String);
+
+impl SecretKey {
+    /** @brief The secret itself; keep any copy made from it short-lived. */
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /** @brief Whether both secrets are equal, in time that depends only on their lengths. */
+    pub fn matches(&self, other: &SecretKey) -> bool {
+        self.0.as_bytes().ct_eq(other.0.as_bytes()).into()
+    }
+}
+
+impl From<String> for SecretKey {
+    fn from(s: String) -> Self {
+        SecretKey(s)
+    }
+}
+
+impl From<&str> for SecretKey {
+    fn from(s: &str) -> Self {
+        SecretKey(s.to_string())
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(<redacted>)")
+    }
+}
+
+/** @brief Fresh x25519 private key, base64 encoded as stored in `client_private_key_b64`. */
+pub fn generate_private_key() -> SecretKey {
+    let secret = x25519_dalek::StaticSecret::random_from_rng(rand::rngs::OsRng);
+    SecretKey(b64(&Zeroizing::new(secret.to_bytes())[..]))
+}
+
+/**
+ * @brief Prove possession of the old key when enrolling a new one.
+ *
+ * The HMAC key is the x25519 shared secret of the old client key and the server key, so the
+ * server can recompute it from its own private key and the old public key it has on record.
+ * @param old_private_b64 Client private key being replaced.
+ * @param server_public_b64 Server WireGuard public key.
+ * @param new_public_b64 Public key being enrolled.
+ */
+pub fn key_proof(old_private_b64: &str, server_public_b64: &str, new_public_b64: &str) -> Result<KeyProof, VpnError> {
+    let old = x25519_dalek::StaticSecret::from(*decode_key("client_private_key_b64", old_private_b64)?);
+    let server = x25519_dalek::PublicKey::from(*decode_key("server_public_key_b64", server_public_b64)?);
+    let shared = old.diffie_hellman(&server);
+    let mut mac = <Hmac::<Sha256> as Mac>::new_from_slice(shared.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(PROOF_LABEL);
+    mac.update(new_public_b64.as_bytes());
+    Ok(KeyProof {
+        previous_key: crate::config::derive_public_key_b64(old_private_b64)?,
+        proof: base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes()),
+    })
+}
+
+/** @brief Current time in the `client_key_created` format. */
+pub fn created_now() -> String {
+    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/**
+ * @brief Parse `client_key_created`.
+ * @param created RFC 3339 timestamp, e.g. `2026-01-31T09:00:00Z`.
+ */
+pub fn parse_created(created: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(created)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("`{created}` is not an RFC 3339 timestamp: {e}"))
+}
+
+/**
+ * @brief Warning for a key older than `key_rotation_days`.
+ * @param cfg Config with `client_key_created`; keys without a recorded date are not judged.
+ * @param now Current time.
+ */
+pub fn rotation_warning(cfg: &ClientConfig, now: DateTime<Utc>) -> Option<String> {
+    if cfg.key_rotation_days == 0 || cfg.client_private_key_b64.is_none() { return None; }
+    let created = parse_created(cfg.client_key_created.as_deref()?).ok()?;
+    let age = (now - created).num_days();
+    (age >= i64::from(cfg.key_rotation_days)).then(|| format!(
+        "the client key is {age} days old (policy: {} days); run `vpn-client rotate-key`", cfg.key_rotation_days
+    ))
+}
+
+/**
+ * @brief Write a file only the current user can read, replacing `path` atomically.
+ *
+ * The content goes to a temp file created with mode 0600 and `O_EXCL`, which is then renamed
+ * over `path`, so the secret is never visible with looser permissions.
+ * @param path File to write.
+ * @param contents New content.
+ */
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), VpnError> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    let _ = fs::remove_file(&tmp);
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    let mut file = opts.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/**
+ * @brief Refuse a file that group or others can access.
+ * @param path Config or key file.
+ * @return `VpnError::InsecurePermissions` with the fix when the mode allows access beyond the owner.
+ */
+pub fn check_private_file(path: &Path) -> Result<(), VpnError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(VpnError::InsecurePermissions(format!(
+                "{} is accessible by group or others (mode {mode:04o}); run `chmod 600 {}`", path.display(), path.display()
+            )));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/** @brief Content of a `private_key_file`. */
+#[derive(Debug, Clone)]
+pub enum StoredKey {
+    /** One base64 key in clear text. */
+    Plain(SecretKey),
+    /** A key encrypted with a passphrase. */
+    Sealed(SealedKey),
+}
+
+/**
+ * @brief Versioned, passphrase-encrypted key file (JSON).
+ *
+ * Argon2id turns the passphrase and salt into a 256-bit key for XChaCha20-Poly1305, which
+ * encrypts the raw 32-byte private key. The format version is bound in as associated data.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub version: u32,
+    /** Always `argon2id` in version 1. */
+    pub kdf: String,
+    /** Argon2 memory cost in KiB. */
+    pub m_cost: u32,
+    /** Argon2 iterations. */
+    pub t_cost: u32,
+    /** Argon2 lanes. */
+    pub p_cost: u32,
+    /** Base64 KDF salt (16 bytes). */
+    pub salt: String,
+    /** Base64 XChaCha20 nonce (24 bytes). */
+    pub nonce: String,
+    /** Base64 encrypted key with its Poly1305 tag. */
+    pub ciphertext: String,
+}
+
+impl SealedKey {
+    /**
+     * @brief Encrypt a private key with a passphrase, using a fresh salt and nonce.
+     * @param key_b64 Client private key (base64).
+     * @param passphrase Passphrase chosen by the user.
+     */
+    pub fn seal(key_b64: &str, passphrase: &str) -> Result<SealedKey, VpnError> {
+        let key = decode_key("client private key", key_b64)?;
+        let (mut salt, mut nonce) = ([0u8; 16], [0u8; 24]);
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let params = Params::default();
+        let mut sealed = SealedKey {
+            version: SEALED_KEY_VERSION,
+            kdf: "argon2id".into(),
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            salt: b64(&salt),
+            nonce: b64(&nonce),
+            ciphertext: String::new(),
+        };
+        let cipher = sealed.cipher(passphrase)?;
+        let ct = cipher.encrypt(XNonce::from_slice(&nonce), Payload { msg: &key[..], aad: SEALED_KEY_AAD })
+            .map_err(|_| VpnError::Passphrase("encryption failed".into()))?;
+        sealed.ciphertext = b64(&ct);
+        Ok(sealed)
+    }
+
+    /**
+     * @brief Decrypt the private key.
+     * @param passphrase Passphrase the key was sealed with.
+     * @return The base64 private key; `VpnError::Passphrase` for a wrong passphrase or a damaged file.
+     */
+    pub fn open(&self, passphrase: &str) -> Result<SecretKey, VpnError> {
+        if self.version != SEALED_KEY_VERSION || self.kdf != "argon2id" {
+            return Err(VpnError::Passphrase(format!("unsupported key file format (version {}, kdf {})", self.version, self.kdf)));
+        }
+        let nonce = unb64(&self.nonce).filter(|n| n.len() == 24)
+            .ok_or_else(|| VpnError::Passphrase("damaged key file (nonce)".into()))?;
+        let ct = unb64(&self.ciphertext).ok_or_else(|| VpnError::Passphrase("damaged key file (ciphertext)".into()))?;
+        let key = self.cipher(passphrase)?
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ct, aad: SEALED_KEY_AAD })
+            .map(Zeroizing::new)
+            .map_err(|_| VpnError::Passphrase("wrong passphrase or damaged key file".into()))?;
+        Ok(SecretKey(b64(&key)))
+    }
+
+    fn cipher(&self, passphrase: &str) -> Result<XChaCha20Poly1305, VpnError> {
+        let salt = unb64(&self.salt).ok_or_else(|| VpnError::Passphrase("damaged key file (salt)".into()))?;
+        // the file may come from anywhere; unbounded costs would stall or exhaust the host
+        if self.m_cost > MAX_KDF_M_COST || self.t_cost > MAX_KDF_T_COST || self.p_cost > MAX_KDF_P_COST {
+            return Err(VpnError::Passphrase(format!(
+                "KDF parameters out of range (m_cost {}, t_cost {}, p_cost {}; at most {MAX_KDF_M_COST}, {MAX_KDF_T_COST}, {MAX_KDF_P_COST})",
+                self.m_cost, self.t_cost, self.p_cost
+            )));
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| VpnError::Passphrase(format!("bad KDF parameters: {e}")))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key[..])
+            .map_err(|e| VpnError::Passphrase(format!("key derivation failed: {e}")))?;
+        Ok(XChaCha20Poly1305::new((&*key).into()))
+    }
+}
+
+/**
+ * @brief Read the client private key from `private_key_file`.
+ * @param path Key file holding one base64 key, or a `SealedKey` as JSON.
+ * @return `None` when the file does not exist yet.
+ */
+pub fn read_private_key_file(path: &Path) -> Result<Option<StoredKey>, VpnError> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => Zeroizing::new(c),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let content = content.trim();
+    if content.starts_with('{') {
+        let sealed = serde_json::from_str(content)
+            .map_err(|e| VpnError::InvalidKey(format!("{}: not an encrypted key file: {e}", path.display())))?;
+        return Ok(Some(StoredKey::Sealed(sealed)));
+    }
+    decode_key(&path.display().to_string(), content)?;
+    Ok(Some(StoredKey::Plain(SecretKey(content.to_string()))))
+}
+
+/**
+ * @brief Passphrase from `$VPN_CLIENT_KEY_PASSPHRASE`, or from the file named by `$VPN_CLIENT_KEY_PASSPHRASE_FILE`.
+ * @return `None` when neither is set.
+ */
+pub fn passphrase_from_env() -> Result<Option<SecretKey>, VpnError> {
+    if let Some(p) = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()) {
+        return Ok(Some(SecretKey(p)));
+    }
+    match std::env::var_os(PASSPHRASE_FILE_ENV) {
+        Some(file) => {
+            let p = fs::read_to_string(&file).map(Zeroizing::new)
+                .map_err(|e| VpnError::Passphrase(format!("{}: {e}", Path::new(&file).display())))?;
+            Ok(Some(SecretKey(p.trim_end_matches(['\r', '\n']).to_string())))
+        }
+        None => Ok(None),
+    }
+}
+
+/**
+ * @brief Decrypt an encrypted `private_key_file` into `cfg.client_private_key_b64`.
+ *
+ * The passphrase comes from the environment when set, otherwise from `prompt`. It is kept in
+ * `cfg.key_passphrase` so a rotated key is saved encrypted with it. No-op for plain keys.
+ * @param cfg Config loaded by `load_client_config`.
+ * @param prompt Asks the user for the passphrase; return `VpnError::Passphrase` where nobody can answer.
+ */
+pub fn unlock_key(cfg: &mut ClientConfig, prompt: &dyn Fn() -> Result<SecretKey, VpnError>) -> Result<(), VpnError> {
+    let Some(sealed) = cfg.sealed_key.as_ref().filter(|_| cfg.client_private_key_b64.is_none()) else { return Ok(()) };
+    let passphrase = match passphrase_from_env()? {
+        Some(p) => p,
+        None => prompt()?,
+    };
+    cfg.client_private_key_b64 = Some(sealed.open(passphrase.expose())?);
+    cfg.key_passphrase = Some(passphrase);
+    Ok(())
+}
+
+/** @brief Error for an encrypted key nobody has unlocked; points at the environment variables. */
+pub fn locked_key_error() -> VpnError {
+    VpnError::Passphrase(format!("the key is encrypted; set {PASSPHRASE_ENV} or {PASSPHRASE_FILE_ENV}"))
+}
+
+/** @brief Prompt for callers that cannot ask anyone, such as the daemon. */
+pub fn no_prompt() -> Result<SecretKey, VpnError> {
+    Err(locked_key_error())
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn unb64(s: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD.decode(s).ok()
+}
+
+fn decode_key(field: &str, b64: &str) -> Result<Zeroizing<[u8; 32]>, VpnError> {
+    unb64(b64).map(Zeroizing::new)
+        .and_then(|b| <[u8; 32]>::try_from(b.as_slice()).ok())
+        .map(Zeroizing::new)
+        .ok_or_else(|| VpnError::InvalidKey(format!("{field}: not a base64 32-byte key")))
+}
@@ -1,27 +1,53 @@
-use std::process::Command;
+#[cfg(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows"))]
+use crate::command::run;
+use crate::error::VpnError;
 
 /**
  * @brief Apply a kill switch to force traffic through the VPN interface.
  * @param interface Interface name to allow while blocking other traffic.
  */
-pub fn apply_kill_switch(interface: &str) {
-    if cfg!(target_os = "linux") {
-        let _ = Command::new("iptables").args(["-P", "OUTPUT", "DROP"]).output();
-        let _ = Command::new("iptables").args(["-A", "OUTPUT", "-o", interface, "-j", "ACCEPT"]).output();
-    } else if cfg!(target_os = "windows") {
-        let _ = Command::new("netsh").args(["advfirewall", "set", "allprofiles", "state", "on"]).output();
-    }
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn apply_kill_switch(interface: &str) -> Result<(), VpnError> {
+    run("iptables", &["-P", "OUTPUT", "DROP"])?;
+    run("iptables", &["-A", "OUTPUT", "-o", interface, "-j", "ACCEPT"])?;
+    Ok(())
 }
 
 /**
  * @brief Revert the kill switch rules, restoring normal traffic behavior.
  * @param interface Interface name previously allowed.
  */
-pub fn revert_kill_switch(interface: &str) {
-    if cfg!(target_os = "linux") {
-        let _ = Command::new("iptables").args(["-P", "OUTPUT", "ACCEPT"]).output();
-        let _ = Command::new("iptables").args(["-D", "OUTPUT", "-o", interface, "-j", "ACCEPT"]).output();
-    } else if cfg!(target_os = "windows") {
-        // No-op to avoid toggling global firewall state
-    }
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn revert_kill_switch(interface: &str) -> Result<(), VpnError> {
+    let policy = run("iptables", &["-P", "OUTPUT", "ACCEPT"]);
+    // The rule may already be gone; the policy is what matters for connectivity.
+    let _ = run("iptables", &["-D", "OUTPUT", "-o", interface, "-j", "ACCEPT"]);
+    policy.map(|_| ())
+}
+
+/**
+ * @brief Turn the firewall on for all profiles (Windows best-effort kill switch).
+ * @param _interface Unused on Windows.
+ */
+#[cfg(target_os = "windows")]
+pub fn apply_kill_switch(_interface: &str) -> Result<(), VpnError> {
+    run("netsh", &["advfirewall", "set", "allprofiles", "state", "on"]).map(|_| ())
+}
+
+/** @brief Revert the kill switch (Windows: no-op to avoid toggling global firewall state). */
+#[cfg(target_os = "windows")]
+pub fn revert_kill_switch(_interface: &str) -> Result<(), VpnError> {
+    Ok(())
+}
+
+/** @brief Apply a kill switch (unsupported on this platform or without `linux-net`). */
+#[cfg(not(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows")))]
+pub fn apply_kill_switch(_interface: &str) -> Result<(), VpnError> {
+    Err(VpnError::SystemCommand("kill switch is not supported in this build".into()))
+}
+
+/** @brief Revert the kill switch (stub without platform support). */
+#[cfg(not(any(all(target_os = "linux", feature = "linux-net"), target_os = "windows")))]
+pub fn revert_kill_switch(_interface: &str) -> Result<(), VpnError> {
+    Ok(())
 }
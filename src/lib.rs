@@ -1,30 +1,57 @@
+pub mod auth;
+pub mod browser;
+pub mod captive;
+mod command;
 pub mod config;
+pub mod daemon;
+pub mod dns;
+pub mod domains;
+pub mod enroll;
+pub mod error;
+pub mod filelog;
+pub mod health;
+pub mod http;
+pub mod journal;
+pub mod keys;
+pub mod kill_switch;
+pub mod lock;
+pub mod netwatch;
+pub mod resolver;
+pub mod route;
+pub mod state;
+pub mod system;
+pub mod tls;
+pub mod tunnel;
+pub mod validate;
 use defguard_wireguard_rs::{host::Peer, key::Key, net::IpAddrMask, InterfaceConfiguration};
 use base64::Engine as _;
 use std::str::FromStr;
+pub use error::VpnError;
 
 /**
  * @brief Build the WireGuard interface configuration for the client.
  * @param cfg Loaded client configuration (addresses, endpoint, keys, split/full tunnel).
  * @param ifname Interface name to create/use.
- * @return InterfaceConfiguration populated with one server peer and client settings.
+ * @return InterfaceConfiguration populated with one server peer and client settings; its `prvkey`
+ *         is a plain copy of the private key, so wipe it (`zeroize`) once the interface is configured.
  */
-pub fn build_interface_config(cfg: &crate::config::ClientConfig, ifname: &str) -> Result<InterfaceConfiguration, Box<dyn std::error::Error>> {
-    let server_pubkey_bytes = base64::engine::general_purpose::STANDARD.decode(&cfg.server_public_key_b64)?;
-    if server_pubkey_bytes.len() != 32 { return Err("Server public key must decode to exactly 32 bytes".into()); }
-    let server_pubkey = Key::new(server_pubkey_bytes.try_into().unwrap());
+pub fn build_interface_config(cfg: &crate::config::ClientConfig, ifname: &str) -> Result<InterfaceConfiguration, VpnError> {
+    let server_pubkey_bytes = base64::engine::general_purpose::STANDARD.decode(&cfg.server_public_key_b64)
+        .map_err(|e| VpnError::InvalidKey(format!("server_public_key_b64: {e}")))?;
+    let server_pubkey_arr: [u8; 32] = server_pubkey_bytes.try_into()
+        .map_err(|_| VpnError::InvalidKey("Server public key must decode to exactly 32 bytes".into()))?;
+    let server_pubkey = Key::new(server_pubkey_arr);
     let mut peer = Peer::new(server_pubkey);
-    peer.endpoint = Some(cfg.server_endpoint.parse()?);
+    peer.endpoint = Some(cfg.server_endpoint.parse()
+        .map_err(|_| VpnError::InvalidEndpoint(cfg.server_endpoint.clone()))?);
     peer.persistent_keepalive_interval = Some(cfg.keepalive_secs);
-    if cfg.split_tunnel {
-        peer.allowed_ips.push(IpAddrMask::from_str("10.8.0.0/24")?);
-    } else {
-        peer.allowed_ips.push(IpAddrMask::from_str("0.0.0.0/0")?);
+    for allowed in cfg.effective_allowed_ips() {
+        peer.allowed_ips.push(IpAddrMask::from_str(&allowed).map_err(|_| VpnError::InvalidCidr(allowed.clone()))?);
     }
     let config = InterfaceConfiguration {
         name: ifname.to_string(),
-        prvkey: cfg.client_private_key_b64.clone().ok_or("Missing client private key")?,
-        addresses: vec![cfg.address_cidr.parse()?],
+        prvkey: cfg.client_private_key_b64.as_ref().ok_or_else(|| VpnError::InvalidKey("Missing client private key".into()))?.expose().to_string(),
+        addresses: vec![cfg.address_cidr.parse().map_err(|_| VpnError::InvalidCidr(cfg.address_cidr.clone()))?],
         port: 0,
         peers: vec![peer],
         mtu: None,
@@ -0,0 +1,80 @@
+use crate::error::VpnError;
+use crate::state::runtime_dir;
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+/** @brief Advisory lock file for an interface, e.g. `/run/vpn-client/wg-client.lock`. */
+pub fn lock_path(ifname: &str) -> PathBuf {
+    runtime_dir().join(format!("{ifname}.lock"))
+}
+
+/**
+ * @brief Exclusive per-interface lock held for the lifetime of a tunnel owner.
+ *
+ * Taken with `flock(LOCK_EX | LOCK_NB)` (`LockFileEx` on Windows) and stamped with the
+ * owner's PID. The kernel drops the lock when the process exits, so a crash never leaves
+ * it stuck. The file itself is kept to avoid unlink races between contenders.
+ */
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    ifname: String,
+}
+
+impl InstanceLock {
+    /**
+     * @brief Take the lock for `ifname` or report who holds it.
+     * @param ifname Interface name.
+     * @return The held lock, or `VpnError::AlreadyRunning` with the owner's PID.
+     */
+    pub fn acquire(ifname: &str) -> Result<InstanceLock, VpnError> {
+        let path = lock_path(ifname);
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(VpnError::AlreadyRunning { ifname: ifname.to_string(), pid: read_pid(&mut file) });
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(InstanceLock { file, ifname: ifname.to_string() })
+    }
+
+    /** @brief Interface this lock protects. */
+    pub fn ifname(&self) -> &str { &self.ifname }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+/**
+ * @brief PID of the process currently holding the lock for `ifname`, if any.
+ *
+ * Reads the PID without taking the lock, so it works on a root-owned file and never makes a
+ * concurrent `acquire` fail; the holder empties the file on release, and a PID left behind by a
+ * crash is discarded once that process is gone.
+ * @param ifname Interface name.
+ */
+pub fn lock_owner(ifname: &str) -> Option<u32> {
+    let mut file = File::open(lock_path(ifname)).ok()?;
+    read_pid(&mut file).filter(|pid| crate::state::process_alive(*pid))
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut s = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut s).ok()?;
+    s.trim().parse().ok()
+}
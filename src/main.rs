@@ -1,19 +1,21 @@
 use std::{
-    sync::atomic::{AtomicBool, Ordering},
-    sync::Arc,
+    process::ExitCode,
+    sync::atomic::Ordering,
     thread,
     time::Duration,
 };
 
-use base64::{engine::general_purpose, Engine as _};
-use vpn_client::config::{load_client_config, ensure_client_keys};
+use vpn_client::config::{load_client_config_with, ensure_client_keys, derive_public_key_b64, check_config_file, save_client_config};
+use vpn_client::config::{load_checked_config, migrate_private_key, set_key_passphrase};
 use clap::{Parser, Subcommand};
-mod kill_switch;
-mod filelog;
-mod route;
-mod dns;
-use defguard_wireguard_rs::{WGApi, WireguardInterfaceApi};
-use vpn_client::build_interface_config;
+use vpn_client::config::ClientConfig;
+use vpn_client::daemon::{self, Reply, Request};
+use vpn_client::enroll::{enroll_and_apply, retry_pending_unenroll, rotate_key, unenroll, EnrollOptions, PendingUnenroll};
+use vpn_client::lock::{self, InstanceLock};
+use vpn_client::state;
+use vpn_client::system::{HostSystem, System};
+use vpn_client::tunnel::{self, Tunnel, TunnelStatus};
+use vpn_client::{auth, domains, filelog, keys, VpnError};
 
 #[derive(Parser)]
 #[command(name = "vpn-client")]
@@ -28,206 +30,576 @@ struct Cli {
 #[derive(Subcommand)]
 enum Cmd {
     Init,
-    Connect { #[arg(long)] ifname: Option<String> },
+    /** Send this client's public key to the server and store the settings it assigns. */
+    Enroll {
+        url: String,
+        /** PEM CA bundle to trust for an https:// URL (stored as `enroll_ca_file`). */
+        #[arg(long, value_name = "PATH")] ca_file: Option<String>,
+        /** `sha256/<base64>` pin of the server key (stored as `enroll_spki_pin`). */
+        #[arg(long)] pin: Option<String>,
+        /** Enrollment token for this run (overrides `VPN_CLIENT_ENROLL_TOKEN` and `enroll_token`; not saved). */
+        #[arg(long, conflicts_with = "invite")] token: Option<String>,
+        /** One-time invite code from the administrator; used once and never saved. */
+        #[arg(long)] invite: Option<String>,
+    },
+    /** Ask the server to forget this client's key, then remove the private key from the config. */
+    Unenroll {
+        /** Token for this run (overrides `VPN_CLIENT_ENROLL_TOKEN` and `enroll_token`; not saved). */
+        #[arg(long)] token: Option<String>,
+    },
+    /** Enroll a new client key, authenticated by the current one, and switch running tunnels to it. */
+    RotateKey {
+        /** Token for this run (overrides `VPN_CLIENT_ENROLL_TOKEN` and `enroll_token`; not saved). */
+        #[arg(long)] token: Option<String>,
+    },
+    /** Sign in with the identity provider from `[auth]` (OAuth device code) and store the token. */
+    Login { #[arg(long)] ifname: Option<String> },
+    Connect {
+        #[arg(long)] ifname: Option<String>,
+        /** Clean up leftovers from a crashed run even when no state file describes them. */
+        #[arg(long)] force: bool,
+        /** Seconds to wait for the first handshake (overrides `handshake_timeout_secs`). */
+        #[arg(long, value_name = "SECS")] handshake_timeout: Option<u64>,
+        /** Milliseconds between handshake checks (overrides `handshake_poll_ms`). */
+        #[arg(long, value_name = "MS")] poll_interval: Option<u64>,
+        /** Extra handshake attempts after a timeout (overrides `handshake_retries`). */
+        #[arg(long)] retries: Option<u32>,
+        /** Seconds before the first retry, doubling each time (overrides `retry_backoff_secs`). */
+        #[arg(long, value_name = "SECS")] retry_backoff: Option<u64>,
+    },
     Disconnect,
     Status,
+    /** Restore the network to its pre-VPN state after a crash. */
+    Recover { #[arg(long)] ifname: Option<String> },
     Import { path: String },
     PrintPubkey,
+    /** Run in the background, owning tunnels and serving the control socket. */
+    Daemon {
+        /** Group allowed to use the control socket (mode 0660). */
+        #[arg(long)] group: Option<String>,
+    },
+    /** Stream tunnel state changes from the running daemon. */
+    Events,
+    /** Manage where and how the client private key is stored. */
+    Key {
+        #[command(subcommand)]
+        action: KeyCmd,
+    },
+    /** Inspect the configuration file. */
+    Config {
+        #[command(subcommand)]
+        action: ConfigCmd,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Subcommand)]
+enum KeyCmd {
+    /** Move an inline `client_private_key_b64` into a 0600 key file referenced by `private_key_file`. */
+    Migrate {
+        /** Key file to create, relative to the config's directory (default: the config name with `.key`). */
+        #[arg(long, value_name = "PATH")] to: Option<String>,
+    },
+    /** Encrypt the key file with a passphrase (Argon2id + XChaCha20-Poly1305), migrating an inline key first. */
+    Encrypt,
+    /** Store the key file in clear text again. */
+    Decrypt,
+    /** Re-encrypt the key file with a new passphrase. */
+    ChangePassphrase,
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /** Validate every field and report all problems without connecting. */
+    Check,
+}
+
+fn main() -> ExitCode {
     env_logger::init();
     let cli = Cli::parse();
-    let cfg_path = cli.config.as_ref().map(|p| std::path::PathBuf::from(p));
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), VpnError> {
+    let cfg_path = cli.config.as_ref().map(std::path::PathBuf::from);
     match cli.cmd {
         Cmd::Init => {
-            let cfg = load_client_config(cfg_path.clone())?;
-            let _ = ensure_client_keys(cfg, cfg_path.clone())?;
+            let cfg = ensure_client_keys(load_unlocked(cfg_path.clone())?, cfg_path.clone())?;
+            let sk_b64 = cfg.client_private_key_b64.as_ref().map(keys::SecretKey::expose).unwrap_or_default();
+            println!("Client public key: {}", derive_public_key_b64(sk_b64)?);
         }
-        Cmd::Connect { ifname } => {
-            let running = Arc::new(AtomicBool::new(true));
-            let r = running.clone();
-            ctrlc::set_handler(move || {
-                r.store(false, Ordering::SeqCst);
-                println!("\nShutting down...");
-            })?;
-            let cfg = load_client_config(cfg_path.clone())?;
-            let cfg = ensure_client_keys(cfg, cfg_path.clone())?;
-            let ifname = ifname.unwrap_or_else(|| cfg.interface_name.clone());
-            let client_private_key_b64 = cfg.client_private_key_b64.clone().unwrap();
-            if let Some(url) = &cfg.enroll_url {
-                // POST client public key to server enrollment endpoint
-                let sk_bytes = general_purpose::STANDARD.decode(&client_private_key_b64)?;
-                let arr: [u8;32] = sk_bytes.try_into().map_err(|_| "Invalid key length")?;
-                let secret = x25519_dalek::StaticSecret::from(arr);
-                let public = x25519_dalek::PublicKey::from(&secret);
-                let pub_b64 = general_purpose::STANDARD.encode(public.as_bytes());
-                if url.starts_with("http://") {
-                    // naive HTTP client
-                    let (host_port, path) = {
-                        let s = url.trim_start_matches("http://");
-                        let pos = s.find('/').unwrap_or(s.len());
-                        let hp = &s[..pos];
-                        let p = &s[pos..];
-                        (hp.to_string(), if p.is_empty() { "/".to_string() } else { p.to_string() })
-                    };
-                    let mut parts = host_port.split(':');
-                    let host = parts.next().unwrap_or("127.0.0.1");
-                    let port: u16 = parts.next().unwrap_or("8080").parse().unwrap_or(8080);
-                    let mut stream = std::net::TcpStream::connect((host, port))?;
-                    let body = pub_b64.clone();
-                    let req = format!(
-                        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                        path, host, body.len(), body
-                    );
-                    use std::io::Write;
-                    stream.write_all(req.as_bytes())?;
-                    use std::io::Read;
-                    let mut resp = String::new();
-                    stream.read_to_string(&mut resp)?;
-                    if !resp.starts_with("HTTP/1.1 200") && !resp.starts_with("HTTP/1.0 200") {
-                        return Err("Enrollment failed".into());
-                    }
+        Cmd::Enroll { url, ca_file, pin, token, invite } => {
+            let mut cfg = ensure_client_keys(load_unlocked(cfg_path.clone())?, cfg_path.clone())?;
+            cfg.enroll_url = Some(url);
+            if ca_file.is_some() { cfg.enroll_ca_file = ca_file; }
+            if pin.is_some() { cfg.enroll_spki_pin = pin; }
+            let issues = vpn_client::validate::validate_config(&cfg);
+            if !issues.is_empty() { return Err(VpnError::InvalidConfig(issues)); }
+            let mut opts = EnrollOptions::from_config(&cfg)?;
+            let explicit = token.or(invite).map(keys::SecretKey::from);
+            if explicit.is_none() {
+                if let Some(t) = sso_token(&cfg, &cfg.interface_name)? { opts.token = Some(t); }
+            }
+            // an invite is for the new key only; earlier deregistrations use the standing credentials
+            retry_unenroll(&mut cfg, &opts);
+            if explicit.is_some() { opts.token = explicit; }
+            if enroll_and_apply(&mut cfg, &opts)? {
+                println!("Enrolled: address {}, endpoint {}", cfg.address_cidr, cfg.server_endpoint);
+                for ep in &cfg.fallback_endpoints { println!("  fallback endpoint {ep}"); }
+            } else if cfg.server_public_key_b64.is_empty() {
+                return Err(VpnError::Enrollment("the server accepted the key but sent no configuration; run `vpn-client import`".into()));
+            } else {
+                println!("Enrolled; the server sent no configuration, keeping the current settings");
+            }
+            save_client_config(&cfg, cfg_path.clone())?;
+        }
+        Cmd::Unenroll { token } => {
+            let mut cfg = load_unlocked(cfg_path.clone())?;
+            let opts = enroll_options(&cfg, token)?;
+            retry_unenroll(&mut cfg, &opts);
+            let Some(private_key) = &cfg.client_private_key_b64 else {
+                save_client_config(&cfg, cfg_path.clone())?;
+                println!("No client key to deregister");
+                return Ok(());
+            };
+            let url = cfg.effective_unenroll_url()
+                .ok_or_else(|| VpnError::Enrollment("neither unenroll_url nor enroll_url is set".into()))?
+                .to_string();
+            let public_key = derive_public_key_b64(private_key.expose())?;
+            match unenroll(&url, &public_key, &opts) {
+                Ok(()) => println!("Deregistered {public_key}"),
+                Err(VpnError::Connectivity(m)) => {
+                    println!("Server unreachable ({m}); the deregistration will be retried by the next enroll, connect or unenroll");
+                    cfg.pending_unenroll.push(PendingUnenroll { url, public_key });
                 }
+                Err(e) => return Err(e),
             }
-            let mut cfg2 = cfg.clone();
-            cfg2.client_private_key_b64 = Some(client_private_key_b64.to_string());
-            let config = build_interface_config(&cfg2, &ifname)?;
-            println!("Creating interface {ifname} and connecting...");
-            let wgapi = WGApi::<defguard_wireguard_rs::Kernel>::new(ifname.clone())?;
-            wgapi.create_interface()?;
-            #[cfg(target_os = "windows")]
-            {
-                wgapi.configure_interface(&config, &[], &[])?;
-            }
-            #[cfg(not(target_os = "windows"))]
-            {
-                wgapi.configure_interface(&config)?;
-            }
-            if cfg.kill_switch { kill_switch::apply_kill_switch(&ifname); }
-            let original = if !cfg.split_tunnel { route::snapshot_default() } else { None };
-            let _dns_snap = if !cfg.split_tunnel && !cfg!(target_os = "windows") { Some(dns::snapshot_dns()) } else { None };
-            if !cfg.split_tunnel {
-                if let Some((gw, dev)) = original.as_ref() {
-                    let ep_ip = cfg.server_endpoint.split(':').next().unwrap_or("127.0.0.1");
-                    route::host_route_to_endpoint(ep_ip, gw, dev);
+            cfg.forget_key();
+            save_client_config(&cfg, cfg_path.clone())?;
+            println!("Private key removed from the config");
+        }
+        Cmd::RotateKey { token } => {
+            let cfg = load_unlocked(cfg_path.clone())?;
+            let rotated = rotate_key(&cfg, &enroll_options(&cfg, token)?)?;
+            save_client_config(&rotated, cfg_path.clone())?;
+            let new_key = rotated.client_private_key_b64.as_ref().map(keys::SecretKey::expose).unwrap_or_default();
+            println!("New client public key: {}", derive_public_key_b64(new_key)?);
+            if let Some(reply) = via_daemon(&Request::SwapKey)? {
+                print_reply(&reply);
+            } else if let Some(pid) = lock::lock_owner(&rotated.interface_name) {
+                println!("The running connect (pid {pid}) switches to the new key within a few seconds");
+            }
+        }
+        Cmd::Login { ifname } => {
+            let cfg = load_client_config_with(cfg_path.clone(), &print_warning)?;
+            let auth_cfg = cfg.auth.as_ref().ok_or_else(|| VpnError::Auth("no [auth] section in the config".into()))?;
+            let name = ifname.unwrap_or_else(|| cfg.interface_name.clone());
+            auth::login(auth_cfg, &name, &auth::tls_settings(&cfg)?, &show_device_code)?;
+            println!("Logged in; token stored in {}", auth::token_path(&name).display());
+        }
+        Cmd::Connect { ifname, force, handshake_timeout, poll_interval, retries, retry_backoff } => {
+            let overridden = handshake_timeout.is_some() || poll_interval.is_some() || retries.is_some() || retry_backoff.is_some();
+            if let Some(reply) = via_daemon(&Request::Up { ifname: ifname.clone(), force })? {
+                if overridden { println!("note: handshake flags are ignored; the daemon uses its own config"); }
+                print_reply(&reply);
+                return Ok(());
+            }
+            let cfg = load_unlocked(cfg_path.clone())?;
+            let mut cfg = ensure_client_keys(cfg, cfg_path.clone())?;
+            if let Some(v) = handshake_timeout { cfg.handshake_timeout_secs = v; }
+            if let Some(v) = poll_interval { cfg.handshake_poll_ms = v; }
+            if let Some(v) = retries { cfg.handshake_retries = v; }
+            if let Some(v) = retry_backoff { cfg.retry_backoff_secs = v; }
+            if overridden {
+                let issues = vpn_client::validate::validate_config(&cfg);
+                if !issues.is_empty() { return Err(VpnError::InvalidConfig(issues)); }
+            }
+            let name = ifname.clone().unwrap_or_else(|| cfg.interface_name.clone());
+            if cfg.enroll_url.is_some() || !cfg.pending_unenroll.is_empty() {
+                let mut opts = EnrollOptions::from_config(&cfg)?;
+                if let Some(t) = sso_token(&cfg, &name)? { opts.token = Some(t); }
+                if !cfg.pending_unenroll.is_empty() {
+                    retry_unenroll(&mut cfg, &opts);
+                    save_client_config(&cfg, cfg_path.clone())?;
                 }
-                dns::apply_full_tunnel_dns(&ifname);
-            }
-            let start = std::time::Instant::now();
-            let timeout = std::time::Duration::from_secs(20);
-            loop {
-                if let Ok(data) = wgapi.read_interface_data() {
-                    let mut ok = false;
-                    for (_, p) in &data.peers {
-                        if p.last_handshake.is_some() { ok = true; break; }
+            }
+            let _lock = InstanceLock::acquire(&name)?;
+            let actions = state::clear_leftovers(&HostSystem, &name, force, cfg.kill_switch)?;
+            if !actions.is_empty() {
+                println!("Cleaned up after a previous run on {name}:");
+                for action in actions { println!("  {action}"); }
+            }
+            let mut tunnel = Tunnel::new(cfg, ifname);
+            tunnel.log_to(filelog::LogSink::file(filelog::DEFAULT_LOG));
+            let stop = tunnel.stop_handle();
+            ctrlc::set_handler(move || {
+                stop.store(true, Ordering::SeqCst);
+                println!("\nShutting down...");
+            }).map_err(|e| VpnError::SystemCommand(format!("Failed to install Ctrl+C handler: {e}")))?;
+            let events = tunnel.subscribe();
+            thread::spawn(move || {
+                for ev in events {
+                    if ev.to == tunnel::TunnelState::Preflight && !ev.detail.is_empty() {
+                        println!("{}", ev.detail);
                     }
-                    if ok { break; }
+                    filelog::write_line(filelog::DEFAULT_LOG, &format!("State {} -> {} {}", ev.from, ev.to, ev.detail));
                 }
-                if start.elapsed() >= timeout {
-                    filelog::write_line("vpn-client.log", &format!("Handshake timeout for {ifname}"));
-                    let _ = std::process::Command::new("ip").args(["link", "set", &ifname, "down"]).output();
-                    wgapi.remove_interface()?;
-                    return Err("Handshake timeout — server unreachable".into());
-                }
-                thread::sleep(Duration::from_secs(1));
-            }
-            // Connectivity probe over raw IP (no DNS)
-            let probe = std::net::TcpStream::connect_timeout(
-                &"1.1.1.1:443".parse::<std::net::SocketAddr>().unwrap(),
-                std::time::Duration::from_secs(3),
-            );
-            if probe.is_err() {
-                filelog::write_line("vpn-client.log", "Connectivity probe failed after handshake — tearing down");
-                let _ = std::process::Command::new("ip").args(["link", "set", &ifname, "down"]).output();
-                wgapi.remove_interface()?;
-                if cfg.kill_switch { kill_switch::revert_kill_switch(&ifname); }
-                #[cfg(target_os = "windows")]
-                {
-                    dns::restore_dns(&ifname);
-                }
-                #[cfg(not(target_os = "windows"))]
-                {
-                    if let Some(ref snap) = _dns_snap { dns::restore_dns(snap); }
-                }
-                return Err("Connectivity failed — restored network".into());
+            });
+            println!("Creating interface {} and connecting...", tunnel.ifname());
+            tunnel.up()?;
+            if let Err(e) = tunnel.watch_network() {
+                println!("warning: not following network changes: {e}");
             }
             println!("Client is running — handshaking with server...");
-            filelog::write_line("vpn-client.log", &format!("Client connected on {ifname}"));
-            {
-                let host = cfg.server_endpoint.split(':').next().unwrap_or("127.0.0.1");
-                let target = cfg.welcome_url.clone().unwrap_or_else(|| format!("http://{}:8080/", host));
-                if cfg!(target_os = "windows") {
-                    let _ = std::process::Command::new("powershell").args(["-Command", &format!("Start-Process '{}'", target)]).output();
-                } else if cfg!(target_os = "macos") {
-                    let _ = std::process::Command::new("open").arg(&target).output();
-                } else {
-                    let _ = std::process::Command::new("xdg-open").arg(&target).output();
-                }
-            }
+            open_welcome_page(tunnel.config());
             println!("Press Ctrl+C to stop\n");
-            while running.load(Ordering::SeqCst) {
-                thread::sleep(Duration::from_secs(5));
-                if let Ok(data) = wgapi.read_interface_data() {
-                    for (_, p) in &data.peers {
-                        if p.last_handshake.is_some() { let msg = format!("CONNECTED | {} KB sent | {} KB recv", p.tx_bytes / 1024, p.rx_bytes / 1024); println!("{}", msg); filelog::write_line("vpn-client.log", &msg); } else { println!("Still waiting for handshake..."); }
+            let mut cfg_mtime = config_mtime(cfg_path.as_deref());
+            while !tunnel.stop_requested() {
+                for _ in 0..5 {
+                    if tunnel.stop_requested() { break; }
+                    thread::sleep(Duration::from_secs(1));
+                }
+                let mtime = config_mtime(cfg_path.as_deref());
+                let changed = mtime != cfg_mtime;
+                cfg_mtime = mtime;
+                if let Some(rotated) = changed.then(|| rotated_config(cfg_path.clone(), tunnel.config())).flatten() {
+                    match tunnel.swap_key(rotated) {
+                        Ok(()) => println!("Switched to the rotated client key"),
+                        Err(e) => println!("warning: could not switch to the rotated client key: {e}"),
                     }
                 }
+                let st = tunnel.poll();
+                let age = st.handshake_age(std::time::SystemTime::now()).map(|a| a.as_secs());
+                match (st.state, age) {
+                    (tunnel::TunnelState::Connected, Some(age)) => {
+                        let msg = format!("CONNECTED | handshake {age}s ago | {} KB sent | {} KB recv", st.tx_bytes / 1024, st.rx_bytes / 1024);
+                        println!("{}", msg);
+                        filelog::write_line(filelog::DEFAULT_LOG, &msg);
+                    }
+                    (tunnel::TunnelState::Reconnecting, Some(age)) => println!("RECONNECTING | last handshake {age}s ago"),
+                    (tunnel::TunnelState::Reconnecting, None) => println!("RECONNECTING | no handshake yet"),
+                    _ => println!("Still waiting for handshake..."),
+                }
             }
-            drop(wgapi);
-            let _ = std::process::Command::new("ip").args(["link", "set", &ifname, "down"]).output();
-            let wgapi = WGApi::<defguard_wireguard_rs::Kernel>::new(ifname.clone())?;
-            let _ = wgapi.remove_interface();
-            if cfg.kill_switch { kill_switch::revert_kill_switch(&ifname); }
-            if !cfg.split_tunnel { route::restore_default(&original); }
-            #[cfg(target_os = "windows")]
-            {
-                dns::restore_dns(&ifname);
-            }
-            #[cfg(not(target_os = "windows"))]
-            {
-                if let Some(ref snap) = _dns_snap { dns::restore_dns(snap); }
-            }
+            tunnel.down()?;
             println!("Client stopped.");
-            filelog::write_line("vpn-client.log", &format!("Client stopped on {ifname}"));
         }
         Cmd::Disconnect => {
-            let cfg = load_client_config(cfg_path.clone())?;
+            if let Some(reply) = via_daemon(&Request::Down { ifname: None })? {
+                print_reply(&reply);
+                return Ok(());
+            }
+            let cfg = load_client_config_with(cfg_path.clone(), &print_warning)?;
             let ifname = cfg.interface_name.clone();
-            let wgapi = WGApi::<defguard_wireguard_rs::Kernel>::new(ifname.clone())?;
-            let _ = std::process::Command::new("ip").args(["link", "set", &ifname, "down"]).output();
-            wgapi.remove_interface()?;
+            let handled = state::disconnect(&HostSystem, &ifname, Duration::from_secs(10))?;
+            // No state file (or it did not cover the interface): make sure the link is gone.
+            if HostSystem.read_peers(&ifname).is_ok() {
+                HostSystem.remove_interface(&ifname)?;
+            } else if !handled {
+                println!("{ifname} is not connected");
+            }
         }
         Cmd::Status => {
-            let cfg = load_client_config(cfg_path.clone())?;
-            let ifname = cfg.interface_name.clone();
-            let wgapi = WGApi::<defguard_wireguard_rs::Kernel>::new(ifname.clone())?;
-            if let Ok(data) = wgapi.read_interface_data() {
-                for (_, p) in &data.peers {
-                    let hs = p.last_handshake.is_some();
-                    println!("{} {} KB {} KB", hs, p.tx_bytes / 1024, p.rx_bytes / 1024);
-                }
+            let p = cfg_path.clone().unwrap_or_else(|| std::path::PathBuf::from("client.toml"));
+            if let Some(w) = check_config_file(&p).ok().and_then(|r| keys::rotation_warning(&r.config, chrono::Utc::now())) {
+                println!("warning: {w}");
             }
+            if let Some(reply) = via_daemon(&Request::Status { ifname: None })? {
+                print_reply(&reply);
+                return Ok(());
+            }
+            let cfg = load_client_config_with(cfg_path.clone(), &print_warning)?;
+            let left = state::detect_leftovers(&HostSystem, &cfg.interface_name);
+            if left.is_stale() {
+                println!("warning: {} has leftovers from a previous run that did not exit cleanly; run `vpn-client recover`", cfg.interface_name);
+            }
+            print_status(&tunnel::read_status(&cfg.interface_name), lock::lock_owner(&cfg.interface_name));
+        }
+        Cmd::Daemon { group } => run_daemon(cfg_path, group)?,
+        Cmd::Events => watch_events()?,
+        Cmd::Recover { ifname } => {
+            let cfg = load_client_config_with(cfg_path.clone(), &print_warning)?;
+            let ifname = ifname.unwrap_or_else(|| cfg.interface_name.clone());
+            let actions = state::recover(&HostSystem, &ifname, cfg.kill_switch)?;
+            if actions.is_empty() { println!("Nothing to recover for {ifname}"); }
+            for action in actions { println!("{action}"); }
         }
         Cmd::Import { path } => {
             let s = std::fs::read_to_string(path)?;
-            let mut cfg = load_client_config(cfg_path.clone())?;
+            let mut cfg = load_client_config_with(cfg_path.clone(), &print_warning)?;
             let imported: toml::Value = toml::from_str(&s)?;
             if let Some(ep) = imported.get("server_endpoint").and_then(|v| v.as_str()) { cfg.server_endpoint = ep.into(); }
             if let Some(pk) = imported.get("server_public_key_b64").and_then(|v| v.as_str()) { cfg.server_public_key_b64 = pk.into(); }
-            let out = toml::to_string_pretty(&cfg)?;
-            let p = cfg_path.unwrap_or_else(|| std::path::PathBuf::from("client.toml"));
-            std::fs::write(p, out)?;
+            let issues = vpn_client::validate::validate_config(&cfg);
+            if !issues.is_empty() { return Err(VpnError::InvalidConfig(issues)); }
+            save_client_config(&cfg, cfg_path.clone())?;
         }
         Cmd::PrintPubkey => {
-            let cfg = load_client_config(cfg_path.clone())?;
-            let sk_b64 = cfg.client_private_key_b64.clone().ok_or("Missing client private key")?;
-            let sk_bytes = general_purpose::STANDARD.decode(sk_b64)?;
-            let arr: [u8;32] = sk_bytes.try_into().map_err(|_| "Invalid key length")?;
-            let secret = x25519_dalek::StaticSecret::from(arr);
-            let public = x25519_dalek::PublicKey::from(&secret);
-            println!("{}", general_purpose::STANDARD.encode(public.as_bytes()));
+            let cfg = load_unlocked(cfg_path.clone())?;
+            let sk_b64 = cfg.client_private_key_b64.as_ref().ok_or_else(|| VpnError::InvalidKey("Missing client private key".into()))?;
+            println!("{}", derive_public_key_b64(sk_b64.expose())?);
+        }
+        Cmd::Key { action: KeyCmd::Migrate { to } } => {
+            match migrate_private_key(cfg_path.clone(), to)? {
+                Some(file) => println!("Private key moved to {} (mode 0600)", file.display()),
+                None => println!("No inline client_private_key_b64 to migrate"),
+            }
+        }
+        Cmd::Key { action: KeyCmd::Encrypt } => {
+            if let Some(file) = migrate_private_key(cfg_path.clone(), None)? {
+                println!("Private key moved to {} (mode 0600)", file.display());
+            }
+            let cfg = ensure_client_keys(load_unlocked(cfg_path.clone())?, cfg_path.clone())?;
+            if cfg.sealed_key.is_some() {
+                return Err(VpnError::Passphrase("the key is already encrypted; use `vpn-client key change-passphrase`".into()));
+            }
+            set_key_passphrase(cfg, cfg_path.clone(), Some(new_passphrase()?))?;
+            println!("Private key encrypted; connect will ask for the passphrase");
+        }
+        Cmd::Key { action: KeyCmd::Decrypt } => {
+            let cfg = load_unlocked(cfg_path.clone())?;
+            if cfg.sealed_key.is_none() {
+                println!("The private key is not encrypted");
+                return Ok(());
+            }
+            set_key_passphrase(cfg, cfg_path.clone(), None)?;
+            println!("Private key stored in clear text");
+        }
+        Cmd::Key { action: KeyCmd::ChangePassphrase } => {
+            let cfg = load_unlocked(cfg_path.clone())?;
+            if cfg.sealed_key.is_none() {
+                return Err(VpnError::Passphrase("the key is not encrypted; use `vpn-client key encrypt`".into()));
+            }
+            set_key_passphrase(cfg, cfg_path.clone(), Some(new_passphrase()?))?;
+            println!("Passphrase changed");
+        }
+        Cmd::Config { action: ConfigCmd::Check } => {
+            let p = cfg_path.unwrap_or_else(|| std::path::PathBuf::from("client.toml"));
+            let report = check_config_file(&p)?;
+            for w in &report.warnings { println!("warning: {w}"); }
+            if report.config.server_public_key_b64.is_empty() {
+                println!("note: server_public_key_b64 is not set yet; run `vpn-client enroll <url>` or `vpn-client import`");
+            }
+            if !report.issues.is_empty() { return Err(VpnError::InvalidConfig(report.issues)); }
+            println!("{}: OK", p.display());
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @brief Load the config, refusing files others can read, and decrypt an encrypted key file, asking
+ * for the passphrase on the terminal unless `$VPN_CLIENT_KEY_PASSPHRASE(_FILE)` provides it.
+ * @param cfg_path Config file; `None` for `client.toml`.
+ */
+fn load_unlocked(cfg_path: Option<std::path::PathBuf>) -> Result<ClientConfig, VpnError> {
+    let mut cfg = load_checked_config(cfg_path, &print_warning)?;
+    keys::unlock_key(&mut cfg, &|| read_passphrase("Key passphrase: "))?;
+    Ok(cfg)
+}
+
+/** @brief Report a config warning on stderr. */
+fn print_warning(w: &str) {
+    eprintln!("warning: {w}");
+}
+
+/** @brief Read a passphrase from the terminal without echoing it. */
+fn read_passphrase(prompt: &str) -> Result<keys::SecretKey, VpnError> {
+    rpassword::prompt_password(prompt).map(keys::SecretKey::from).map_err(|e| VpnError::Passphrase(format!(
+        "cannot read it from the terminal ({e}); set {} or {}", keys::PASSPHRASE_ENV, keys::PASSPHRASE_FILE_ENV
+    )))
+}
+
+/** @brief Ask for a new passphrase twice. */
+fn new_passphrase() -> Result<keys::SecretKey, VpnError> {
+    let first = read_passphrase("New key passphrase: ")?;
+    if first.expose().is_empty() {
+        return Err(VpnError::Passphrase("the passphrase must not be empty".into()));
+    }
+    if !read_passphrase("Repeat the passphrase: ")?.matches(&first) {
+        return Err(VpnError::Passphrase("the passphrases do not match".into()));
+    }
+    Ok(first)
+}
+
+/**
+ * @brief Open the welcome page in the user's browser once the tunnel is up.
+ * @param cfg Client configuration (welcome URL or endpoint host).
+ */
+fn open_welcome_page(cfg: &ClientConfig) {
+    let host = cfg.server_endpoint.split(':').next().unwrap_or("127.0.0.1");
+    let target = cfg.welcome_url.clone().unwrap_or_else(|| format!("http://{}:8080/", host));
+    vpn_client::browser::open_url(&target);
+}
+
+/**
+ * @brief SSO access token for enrollment, signing in interactively when none is stored.
+ * @param cfg Client configuration; `None` is returned when it has no `[auth]` section.
+ * @param ifname Interface whose token cache is used.
+ */
+fn sso_token(cfg: &ClientConfig, ifname: &str) -> Result<Option<keys::SecretKey>, VpnError> {
+    let Some(auth_cfg) = &cfg.auth else { return Ok(None) };
+    let tls = auth::tls_settings(cfg)?;
+    let token = match auth::current_token(auth_cfg, ifname, &tls) {
+        Ok(Some(t)) => t,
+        Ok(None) | Err(VpnError::Auth(_)) => auth::login(auth_cfg, ifname, &tls, &show_device_code)?,
+        Err(e) => return Err(e),
+    };
+    Ok(Some(token.access_token))
+}
+
+/**
+ * @brief Enrollment settings from the config with the token for this run: `--token`, else the SSO token.
+ * @param cfg Client configuration.
+ * @param token Token given on the command line.
+ */
+fn enroll_options(cfg: &ClientConfig, token: Option<String>) -> Result<EnrollOptions, VpnError> {
+    let mut opts = EnrollOptions::from_config(cfg)?;
+    match token {
+        Some(t) => opts.token = Some(t.into()),
+        None => if let Some(t) = sso_token(cfg, &cfg.interface_name)? { opts.token = Some(t) },
+    }
+    Ok(opts)
+}
+
+/** @brief Modification time of the config file, which `rotate-key` rewrites. */
+fn config_mtime(path: Option<&std::path::Path>) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path.unwrap_or(std::path::Path::new("client.toml"))).and_then(|m| m.modified()).ok()
+}
+
+/**
+ * @brief The config file's settings once `rotate-key` has replaced the key a running tunnel uses.
+ *
+ * Called only when `config_mtime` changes, so an encrypted key is not unlocked over and over.
+ * @param path Config file; `None` for `client.toml`.
+ * @param running Config the tunnel runs with; its handshake settings (possibly from flags) are kept.
+ */
+fn rotated_config(path: Option<std::path::PathBuf>, running: &ClientConfig) -> Option<ClientConfig> {
+    let mut cfg = match load_checked_config(path, &print_warning) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            println!("warning: not re-reading the config: {e}");
+            return None;
         }
+    };
+    let passphrase = running.key_passphrase.clone();
+    keys::unlock_key(&mut cfg, &|| passphrase.clone().ok_or_else(keys::locked_key_error)).ok()?;
+    // compare the public halves: no secret-dependent timing and no `PartialEq` on keys
+    let public = |c: &ClientConfig| c.client_private_key_b64.as_ref().and_then(|k| derive_public_key_b64(k.expose()).ok());
+    if public(&cfg).is_none() || public(&cfg) == public(running) {
+        return None;
+    }
+    cfg.handshake_timeout_secs = running.handshake_timeout_secs;
+    cfg.handshake_poll_ms = running.handshake_poll_ms;
+    cfg.handshake_retries = running.handshake_retries;
+    cfg.retry_backoff_secs = running.retry_backoff_secs;
+    Some(cfg)
+}
+
+/**
+ * @brief Retry deregistrations left over from an `unenroll` that could not reach the server.
+ * @param cfg Config whose `pending_unenroll` list is updated; the caller saves it.
+ * @param opts Credentials and TLS trust for the requests.
+ */
+fn retry_unenroll(cfg: &mut ClientConfig, opts: &EnrollOptions) {
+    for (pending, res) in retry_pending_unenroll(cfg, opts) {
+        match res {
+            Ok(()) => println!("Deregistered earlier key {}", pending.public_key),
+            Err(VpnError::Connectivity(m)) => println!("warning: still cannot deregister {}: {m}", pending.public_key),
+            Err(e) => println!("warning: server refused to deregister {}: {e}; dropping the request", pending.public_key),
+        }
+    }
+}
+
+/** @brief Tell the user where to sign in and open the page in the browser. */
+fn show_device_code(code: &auth::DeviceCode) {
+    println!("To sign in, visit {} and enter the code {}", code.verification_uri, code.user_code);
+    vpn_client::browser::open_url(code.browser_url());
+}
+
+/**
+ * @brief Print a status line, the owning process and the tunnel domain mapping.
+ * @param st Tunnel status.
+ * @param owner PID holding the interface lock, if any.
+ */
+fn print_status(st: &TunnelStatus, owner: Option<u32>) {
+    println!("{} {} KB {} KB", st.last_handshake.is_some(), st.tx_bytes / 1024, st.rx_bytes / 1024);
+    match owner {
+        Some(pid) => println!("{} owned by process {pid}", st.ifname),
+        None => println!("{} not owned by any process", st.ifname),
+    }
+    let now = domains::unix_now();
+    for e in &st.domains {
+        println!("{} -> {} (expires in {}s)", e.domain, e.addr, e.expires_at.saturating_sub(now));
+    }
+}
+
+/** @brief Print a daemon reply the way the local command would. */
+fn print_reply(reply: &Reply) {
+    match reply {
+        Reply::Ok { message } => println!("{message}"),
+        Reply::Status { status, owner_pid } => print_status(status, *owner_pid),
+        Reply::Event { ifname, event } => println!("{ifname}: {} -> {} {}", event.from, event.to, event.detail),
+        Reply::Error { message, .. } => eprintln!("Error: {message}"),
+    }
+}
+
+/**
+ * @brief Forward a request to the daemon when one is listening.
+ * @return The daemon's reply, or `None` when the command should run locally.
+ */
+#[cfg(unix)]
+fn via_daemon(req: &Request) -> Result<Option<Reply>, VpnError> {
+    match daemon::DaemonClient::connect(&daemon::socket_path()) {
+        Ok(mut client) => client.request(req).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(not(unix))]
+fn via_daemon(_req: &Request) -> Result<Option<Reply>, VpnError> { Ok(None) }
+
+/**
+ * @brief Run the daemon until SIGINT/SIGTERM, then tear down its tunnels.
+ * @param cfg_path Config file, also re-read on `reload`.
+ * @param group Group allowed to use the control socket.
+ */
+#[cfg(unix)]
+fn run_daemon(cfg_path: Option<std::path::PathBuf>, group: Option<String>) -> Result<(), VpnError> {
+    let cfg = ensure_client_keys(load_unlocked(cfg_path.clone())?, cfg_path.clone())?;
+    let d = daemon::Daemon::new(cfg, cfg_path);
+    d.log_to(filelog::LogSink::file(filelog::DEFAULT_LOG));
+    let stop = d.stop_handle();
+    ctrlc::set_handler(move || stop.store(true, Ordering::SeqCst))
+        .map_err(|e| VpnError::SystemCommand(format!("Failed to install Ctrl+C handler: {e}")))?;
+    let path = daemon::socket_path();
+    println!("Daemon listening on {}", path.display());
+    d.serve(&path, group.as_deref())
+}
+
+#[cfg(not(unix))]
+fn run_daemon(_cfg_path: Option<std::path::PathBuf>, _group: Option<String>) -> Result<(), VpnError> {
+    Err(VpnError::SystemCommand("daemon mode needs Unix domain sockets".into()))
+}
+
+/** @brief Print every tunnel state change reported by the daemon until it exits. */
+#[cfg(unix)]
+fn watch_events() -> Result<(), VpnError> {
+    let mut client = daemon::DaemonClient::connect(&daemon::socket_path())
+        .map_err(|e| VpnError::SystemCommand(format!("daemon is not running: {e}")))?;
+    client.request(&Request::SubscribeEvents)?;
+    while let Some(reply) = client.next_reply()? {
+        print_reply(&reply);
     }
     Ok(())
 }
+
+#[cfg(not(unix))]
+fn watch_events() -> Result<(), VpnError> {
+    Err(VpnError::SystemCommand("daemon mode needs Unix domain sockets".into()))
+}
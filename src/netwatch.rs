@@ -0,0 +1,118 @@
+use crate::error::VpnError;
+
+/** @brief Kind of network change reported by rtnetlink. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetEvent {
+    /** An interface appeared, disappeared or changed state. */
+    Link,
+    /** An address was added to or removed from an interface. */
+    Address,
+    /** A route changed; `default` is set when it was a default route (prefix length 0). */
+    Route { default: bool },
+}
+
+const NLMSG_HDRLEN: usize = 16;
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+
+/**
+ * @brief Decode the rtnetlink messages in one datagram.
+ *
+ * Only the `nlmsghdr` (and, for routes, the `rtmsg` destination length) is read, so this
+ * works on synthetic buffers as well as on kernel output.
+ * @param buf Datagram received from a `NETLINK_ROUTE` socket.
+ * @return One event per link, address or route message; other messages are skipped.
+ */
+pub fn parse_events(buf: &[u8]) -> Vec<NetEvent> {
+    let mut out = Vec::new();
+    let mut off = 0;
+    while off + NLMSG_HDRLEN <= buf.len() {
+        let len = u32::from_ne_bytes(buf[off..off + 4].try_into().unwrap()) as usize;
+        let kind = u16::from_ne_bytes(buf[off + 4..off + 6].try_into().unwrap());
+        if len < NLMSG_HDRLEN || off + len > buf.len() { break; }
+        let payload = &buf[off + NLMSG_HDRLEN..off + len];
+        match kind {
+            RTM_NEWLINK | RTM_DELLINK => out.push(NetEvent::Link),
+            RTM_NEWADDR | RTM_DELADDR => out.push(NetEvent::Address),
+            // rtmsg: family, dst_len, ...
+            RTM_NEWROUTE | RTM_DELROUTE => out.push(NetEvent::Route { default: payload.get(1) == Some(&0) }),
+            _ => {}
+        }
+        off += (len + 3) & !3;
+    }
+    out
+}
+
+/**
+ * @brief Encode one bare rtnetlink message, e.g. for feeding synthetic events to `parse_events`.
+ * @param kind Message type (`RTM_NEWROUTE` is 24).
+ * @param payload Message body following the header.
+ */
+pub fn encode_message(kind: u16, payload: &[u8]) -> Vec<u8> {
+    let len = NLMSG_HDRLEN + payload.len();
+    let mut out = Vec::with_capacity((len + 3) & !3);
+    out.extend_from_slice(&(len as u32).to_ne_bytes());
+    out.extend_from_slice(&kind.to_ne_bytes());
+    out.extend_from_slice(&[0; 10]);
+    out.extend_from_slice(payload);
+    out.resize((len + 3) & !3, 0);
+    out
+}
+
+/**
+ * @brief Non-blocking subscription to link, address and route changes (IPv4 and IPv6).
+ *
+ * `drain` never waits, so the watcher can be checked from an existing poll loop.
+ */
+#[cfg(target_os = "linux")]
+pub struct NetWatcher {
+    socket: netlink_sys::Socket,
+}
+
+#[cfg(target_os = "linux")]
+impl NetWatcher {
+    /** @brief Open a `NETLINK_ROUTE` socket joined to the link, address and route groups. */
+    pub fn open() -> Result<NetWatcher, VpnError> {
+        use netlink_sys::{protocols::NETLINK_ROUTE, Socket, SocketAddr};
+        const RTMGRP_LINK: u32 = 0x1;
+        const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+        const RTMGRP_IPV4_ROUTE: u32 = 0x40;
+        const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+        const RTMGRP_IPV6_ROUTE: u32 = 0x400;
+        let groups = RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_IFADDR | RTMGRP_IPV6_ROUTE;
+        let mut socket = Socket::new(NETLINK_ROUTE)?;
+        socket.bind(&SocketAddr::new(0, groups))?;
+        socket.set_non_blocking(true)?;
+        Ok(NetWatcher { socket })
+    }
+
+    /** @brief Every event received since the last call; empty when nothing changed. */
+    pub fn drain(&self) -> Vec<NetEvent> {
+        let mut out = Vec::new();
+        let mut buf = vec![0u8; 16 * 1024];
+        while let Ok(n) = self.socket.recv(&mut &mut buf[..], 0) {
+            if n == 0 { break; }
+            out.extend(parse_events(&buf[..n.min(buf.len())]));
+        }
+        out
+    }
+}
+
+/** @brief Network change watcher (unsupported on this platform). */
+#[cfg(not(target_os = "linux"))]
+pub struct NetWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl NetWatcher {
+    /** @brief Always fails: rtnetlink is Linux-only. */
+    pub fn open() -> Result<NetWatcher, VpnError> {
+        Err(VpnError::SystemCommand("network change events are not supported on this platform".into()))
+    }
+
+    /** @brief Never reports events. */
+    pub fn drain(&self) -> Vec<NetEvent> { Vec::new() }
+}
@@ -0,0 +1,115 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use crate::error::VpnError;
+
+/** @brief TTL assumed for answers from the system resolver, which does not expose one. */
+pub const FALLBACK_TTL_SECS: u32 = 60;
+
+/**
+ * @brief Return the first `nameserver` entry from `/etc/resolv.conf`, if any.
+ */
+pub fn system_nameserver() -> Option<IpAddr> {
+    let content = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("nameserver"), Some(addr)) => addr.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/**
+ * @brief Send a single A query over UDP and return the answers with their TTLs.
+ * @param server DNS server to ask.
+ * @param name Fully qualified domain name.
+ * @param timeout Read/write timeout for the exchange.
+ * @return IPv4 addresses with their TTL in seconds.
+ */
+pub fn query_a(server: SocketAddr, name: &str, timeout: Duration) -> Result<Vec<(Ipv4Addr, u32)>, VpnError> {
+    let bind = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let sock = UdpSocket::bind(bind)?;
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+    let id: u16 = rand::random();
+    sock.send_to(&build_query(id, name)?, server)?;
+    let mut buf = [0u8; 1500];
+    let (n, _) = sock.recv_from(&mut buf)?;
+    parse_response(id, &buf[..n])
+}
+
+/**
+ * @brief Resolve a name to addresses with TTLs, preferring the configured nameserver.
+ * @param name Domain name to resolve.
+ * @return Addresses with TTL in seconds; empty when resolution fails.
+ */
+pub fn resolve_with_ttl(name: &str) -> Vec<(IpAddr, u32)> {
+    if let Some(ns) = system_nameserver() {
+        if let Ok(answers) = query_a(SocketAddr::new(ns, 53), name, Duration::from_secs(3)) {
+            if !answers.is_empty() {
+                return answers.into_iter().map(|(ip, ttl)| (IpAddr::V4(ip), ttl)).collect();
+            }
+        }
+    }
+    match (name, 0).to_socket_addrs() {
+        Ok(addrs) => addrs.filter(|a| a.is_ipv4()).map(|a| (a.ip(), FALLBACK_TTL_SECS)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn build_query(id: u16, name: &str) -> Result<Vec<u8>, VpnError> {
+    let mut q = Vec::with_capacity(32 + name.len());
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(VpnError::ConfigParse(format!("Invalid domain name: {name}")));
+        }
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    q.extend_from_slice(&[0, 1, 0, 1]);
+    Ok(q)
+}
+
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 { return Some(pos + 1); }
+        if len & 0xC0 == 0xC0 { return Some(pos + 2); }
+        pos += 1 + len;
+    }
+}
+
+fn parse_response(id: u16, msg: &[u8]) -> Result<Vec<(Ipv4Addr, u32)>, VpnError> {
+    let truncated = || VpnError::Connectivity("Truncated DNS response".into());
+    if msg.len() < 12 || u16::from_be_bytes([msg[0], msg[1]]) != id {
+        return Err(VpnError::Connectivity("Unexpected DNS response".into()));
+    }
+    let rcode = msg[3] & 0x0F;
+    if rcode != 0 {
+        return Err(VpnError::Connectivity(format!("DNS server returned rcode {rcode}")));
+    }
+    let qdcount = u16::from_be_bytes([msg[4], msg[5]]);
+    let ancount = u16::from_be_bytes([msg[6], msg[7]]);
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(msg, pos).ok_or_else(truncated)? + 4;
+    }
+    let mut out = Vec::new();
+    for _ in 0..ancount {
+        pos = skip_name(msg, pos).ok_or_else(truncated)?;
+        let rr = msg.get(pos..pos + 10).ok_or_else(truncated)?;
+        let rtype = u16::from_be_bytes([rr[0], rr[1]]);
+        let ttl = u32::from_be_bytes([rr[4], rr[5], rr[6], rr[7]]);
+        let rdlen = u16::from_be_bytes([rr[8], rr[9]]) as usize;
+        pos += 10;
+        let rdata = msg.get(pos..pos + rdlen).ok_or_else(truncated)?;
+        if rtype == 1 && rdlen == 4 {
+            out.push((Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]), ttl));
+        }
+        pos += rdlen;
+    }
+    Ok(out)
+}
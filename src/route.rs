@@ -1,46 +1,124 @@
-#[cfg(target_os = "linux")]
-use std::process::Command;
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+use crate::command::run;
+use crate::error::VpnError;
+use std::net::IpAddr;
+
+/** @brief Default route of the underlying network, captured before the tunnel comes up. */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DefaultRoute {
+    pub gateway: String,
+    pub device: String,
+}
 
 /**
  * @brief Snapshot default route gateway and device (Linux only).
- * @return Optional pair `(gateway, device)`.
+ * @return Current default route, if one exists.
+ */
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn snapshot_default() -> Option<DefaultRoute> {
+    let s = run("ip", &["route", "show", "default"]).ok()?;
+    parse_default_route(&s)
+}
+
+/**
+ * @brief Parse the first line of `ip route show default` output.
+ * @param s Command output, e.g. `default via 192.168.1.1 dev wlan0 proto dhcp`.
  */
-#[cfg(target_os = "linux")]
-pub fn snapshot_default() -> Option<(String, String)> {
-    let out = Command::new("ip").args(["route", "show", "default"]).output().ok()?;
-    let s = String::from_utf8(out.stdout).ok()?;
-    let parts: Vec<&str> = s.split_whitespace().collect();
-    if parts.len() >= 5 { Some((parts[2].to_string(), parts[4].to_string())) } else { None }
+pub fn parse_default_route(s: &str) -> Option<DefaultRoute> {
+    let parts: Vec<&str> = s.lines().next()?.split_whitespace().collect();
+    let gateway = parts.iter().position(|p| *p == "via").and_then(|i| parts.get(i + 1))?;
+    let device = parts.iter().position(|p| *p == "dev").and_then(|i| parts.get(i + 1))?;
+    Some(DefaultRoute { gateway: gateway.to_string(), device: device.to_string() })
 }
 
 /**
  * @brief Install a host route to the VPN endpoint to avoid recursive routing (Linux only).
  * @param server_ip Endpoint IP.
- * @param gw Gateway.
- * @param dev Device.
+ * @param via Default route of the underlying network.
  */
-#[cfg(target_os = "linux")]
-pub fn host_route_to_endpoint(server_ip: &str, gw: &str, dev: &str) {
-    let _ = Command::new("ip").args(["route", "add", &format!("{}/32", server_ip), "via", gw, "dev", dev]).output();
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn host_route_to_endpoint(server_ip: &str, via: &DefaultRoute) -> Result<(), VpnError> {
+    run("ip", &["route", "replace", &format!("{}/32", server_ip), "via", &via.gateway, "dev", &via.device]).map(|_| ())
+}
+
+/**
+ * @brief Remove the host route installed by `host_route_to_endpoint` (Linux only).
+ * @param server_ip Endpoint IP.
+ */
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn remove_host_route_to_endpoint(server_ip: &str) -> Result<(), VpnError> {
+    run("ip", &["route", "del", &format!("{}/32", server_ip)]).map(|_| ())
 }
 
 /**
  * @brief Restore default route if modified (Linux only).
- * @param _gw_dev Previous `(gateway, device)` snapshot.
+ * @param _original Previous default route snapshot.
  */
-#[cfg(target_os = "linux")]
-pub fn restore_default(_gw_dev: &Option<(String, String)>) {
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn restore_default(_original: &Option<DefaultRoute>) {
     // default route remains managed by WireGuard allowed IPs; no change needed
 }
 
-/** @brief Snapshot default route (stub on non-Linux). */
-#[cfg(not(target_os = "linux"))]
-pub fn snapshot_default() -> Option<(String, String)> { None }
+/**
+ * @brief Route a single address through the VPN interface (Linux only).
+ * @param addr Destination address.
+ * @param ifname VPN interface name.
+ */
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn add_tunnel_route(addr: &IpAddr, ifname: &str) -> Result<(), VpnError> {
+    run("ip", &["route", "replace", &host_prefix(addr), "dev", ifname]).map(|_| ())
+}
+
+/**
+ * @brief Remove a route previously added with `add_tunnel_route` (Linux only).
+ * @param addr Destination address.
+ * @param ifname VPN interface name.
+ */
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn del_tunnel_route(addr: &IpAddr, ifname: &str) -> Result<(), VpnError> {
+    run("ip", &["route", "del", &host_prefix(addr), "dev", ifname]).map(|_| ())
+}
+
+/**
+ * @brief Describe the route the kernel would use for `ip` (Linux only).
+ * @param ip Destination address.
+ * @return First line of `ip route get`, e.g. `203.0.113.5 via 192.168.1.1 dev wlan0 src 192.168.1.20`.
+ */
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+pub fn route_to(ip: &str) -> Option<String> {
+    let s = run("ip", &["route", "get", ip]).ok()?;
+    s.lines().next().map(|l| l.trim().to_string())
+}
+
+#[cfg(all(target_os = "linux", feature = "linux-net"))]
+fn host_prefix(addr: &IpAddr) -> String {
+    if addr.is_ipv4() { format!("{}/32", addr) } else { format!("{}/128", addr) }
+}
+
+/** @brief Snapshot default route (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn snapshot_default() -> Option<DefaultRoute> { None }
+
+/** @brief Describe the route to an address (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn route_to(_ip: &str) -> Option<String> { None }
+
+/** @brief Install host route to endpoint (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn host_route_to_endpoint(_server_ip: &str, _via: &DefaultRoute) -> Result<(), VpnError> { Ok(()) }
+
+/** @brief Remove host route to endpoint (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn remove_host_route_to_endpoint(_server_ip: &str) -> Result<(), VpnError> { Ok(()) }
+
+/** @brief Restore default route (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn restore_default(_original: &Option<DefaultRoute>) {}
 
-/** @brief Install host route to endpoint (stub on non-Linux). */
-#[cfg(not(target_os = "linux"))]
-pub fn host_route_to_endpoint(_server_ip: &str, _gw: &str, _dev: &str) {}
+/** @brief Route an address through the VPN interface (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn add_tunnel_route(_addr: &IpAddr, _ifname: &str) -> Result<(), VpnError> { Ok(()) }
 
-/** @brief Restore default route (stub on non-Linux). */
-#[cfg(not(target_os = "linux"))]
-pub fn restore_default(_gw_dev: &Option<(String, String)>) {}
+/** @brief Remove a tunnel route (stub without Linux support). */
+#[cfg(not(all(target_os = "linux", feature = "linux-net")))]
+pub fn del_tunnel_route(_addr: &IpAddr, _ifname: &str) -> Result<(), VpnError> { Ok(()) }
@@ -0,0 +1,233 @@
+use crate::error::VpnError;
+use crate::journal::{undo, Change};
+use crate::system::System;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/**
+ * @brief Directory holding per-interface runtime files.
+ * @return `$VPN_CLIENT_RUNTIME_DIR` when set, otherwise `/run/vpn-client` (a temp subdirectory on Windows).
+ */
+pub fn runtime_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("VPN_CLIENT_RUNTIME_DIR") {
+        return PathBuf::from(dir);
+    }
+    if cfg!(target_os = "windows") { std::env::temp_dir().join("vpn-client") } else { PathBuf::from("/run/vpn-client") }
+}
+
+/** @brief Runtime state file for an interface, e.g. `/run/vpn-client/wg-client.json`. */
+pub fn state_path(ifname: &str) -> PathBuf {
+    runtime_dir().join(format!("{ifname}.json"))
+}
+
+/** @brief What a running `connect` has changed on the host, so another process can undo it. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeState {
+    pub ifname: String,
+    /** PID of the process that owns the tunnel. */
+    pub pid: u32,
+    /** Applied changes, oldest first. */
+    pub changes: Vec<Change>,
+}
+
+impl RuntimeState {
+    /** @brief Write the state atomically with mode 0600, via `keys::write_private_file`. */
+    pub fn save(&self, path: &Path) -> Result<(), VpnError> {
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        let s = serde_json::to_string_pretty(self).map_err(|e| VpnError::ConfigParse(e.to_string()))?;
+        crate::keys::write_private_file(path, s.as_bytes())
+    }
+
+    /** @brief Read a state file; missing or unreadable files yield `None`. */
+    pub fn load(path: &Path) -> Option<RuntimeState> {
+        let s = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&s).ok()
+    }
+}
+
+/**
+ * @brief Check whether a process is still running.
+ * @param pid Process id.
+ */
+pub fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "windows") {
+        std::process::Command::new("tasklist").args(["/FI", &format!("PID eq {pid}"), "/NH"]).output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).contains(&pid.to_string()))
+            .unwrap_or(false)
+    } else if Path::new("/proc/self").exists() {
+        // unlike `kill -0`, also true for another user's process (e.g. a root daemon seen by `status`)
+        Path::new(&format!("/proc/{pid}")).exists()
+    } else {
+        std::process::Command::new("kill").args(["-0", &pid.to_string()]).output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+}
+
+/**
+ * @brief Ask a process to shut down (SIGTERM; `taskkill` on Windows).
+ * @param pid Process id.
+ */
+pub fn request_exit(pid: u32) -> Result<(), VpnError> {
+    let out = if cfg!(target_os = "windows") {
+        std::process::Command::new("taskkill").args(["/PID", &pid.to_string()]).output()
+    } else {
+        std::process::Command::new("kill").args(["-TERM", &pid.to_string()]).output()
+    };
+    match out {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => Err(VpnError::SystemCommand(format!("kill {pid}: {}", String::from_utf8_lossy(&o.stderr).trim()))),
+        Err(e) => Err(VpnError::SystemCommand(format!("kill {pid}: {e}"))),
+    }
+}
+
+/**
+ * @brief Undo every change listed in a runtime state, newest first.
+ * @param sys System layer to act on.
+ * @param state State read from disk.
+ * @return Errors from undo steps that failed; the remaining steps still run.
+ */
+pub fn replay_undo(sys: &dyn System, state: &RuntimeState) -> Vec<VpnError> {
+    state.changes.iter().rev().filter_map(|c| undo(sys, c).err()).collect()
+}
+
+/**
+ * @brief Stop the tunnel on `ifname` from any process.
+ *
+ * Signals the owning `connect` and waits for it to clean up; if it does not exit in
+ * time (or is already gone), replays the recorded undo steps directly.
+ * @param sys System layer to act on.
+ * @param ifname Interface name.
+ * @param wait How long to wait for the owner to exit.
+ * @return True when a state file was found and handled.
+ */
+pub fn disconnect(sys: &dyn System, ifname: &str, wait: Duration) -> Result<bool, VpnError> {
+    let path = state_path(ifname);
+    let Some(state) = RuntimeState::load(&path) else { return Ok(false) };
+    if state.pid != std::process::id() && process_alive(state.pid) {
+        request_exit(state.pid)?;
+        let start = Instant::now();
+        while start.elapsed() < wait {
+            if !path.exists() { return Ok(true); }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    // The owner is gone or unresponsive: undo from the recorded state.
+    let state = RuntimeState::load(&path).unwrap_or(state);
+    let errors = replay_undo(sys, &state);
+    let _ = fs::remove_file(&path);
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(true),
+    }
+}
+
+/** @brief What a previous run left behind for an interface. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leftovers {
+    /** Runtime state file, if one exists. */
+    pub state: Option<RuntimeState>,
+    /** Whether the PID recorded in the state file is still running. */
+    pub owner_alive: bool,
+    /** Whether the WireGuard interface currently exists. */
+    pub interface_exists: bool,
+}
+
+impl Leftovers {
+    /** @brief Nothing to clean up. */
+    pub fn is_clean(&self) -> bool { self.state.is_none() && !self.interface_exists }
+
+    /** @brief Something is left over and no live process owns it. */
+    pub fn is_stale(&self) -> bool { !self.is_clean() && !self.owner_alive }
+
+    /** @brief A state file from a dead owner: it lists exactly what to undo, so cleanup is safe to automate. */
+    pub fn is_recoverable_automatically(&self) -> bool { self.state.is_some() && !self.owner_alive }
+}
+
+/**
+ * @brief Inspect the runtime state file and the interface for remains of an earlier run.
+ * @param sys System layer to query.
+ * @param ifname Interface name.
+ */
+pub fn detect_leftovers(sys: &dyn System, ifname: &str) -> Leftovers {
+    let state = RuntimeState::load(&state_path(ifname));
+    let owner_alive = state.as_ref().is_some_and(|s| s.pid != std::process::id() && process_alive(s.pid));
+    let interface_exists = sys.read_peers(ifname).is_ok();
+    Leftovers { state, owner_alive, interface_exists }
+}
+
+/**
+ * @brief Restore the network to its pre-VPN state after a crash.
+ *
+ * Replays the undo steps recorded in the state file, then removes the interface if it
+ * still exists. Without a state file the firewall cannot be inferred, so the kill switch
+ * is only reverted when `revert_kill_switch` is set (i.e. the config enables it).
+ * @param sys System layer to act on.
+ * @param ifname Interface name.
+ * @param revert_kill_switch Also reset the firewall when no state file exists.
+ * @return Human-readable list of actions taken.
+ */
+pub fn recover(sys: &dyn System, ifname: &str, revert_kill_switch: bool) -> Result<Vec<String>, VpnError> {
+    let left = detect_leftovers(sys, ifname);
+    if left.owner_alive {
+        let pid = left.state.as_ref().map(|s| s.pid).unwrap_or_default();
+        return Err(VpnError::SystemCommand(format!("{ifname} is owned by running process {pid}; use `disconnect`")));
+    }
+    let mut actions = Vec::new();
+    let mut errors = Vec::new();
+    match &left.state {
+        Some(state) => {
+            for change in state.changes.iter().rev() {
+                match undo(sys, change) {
+                    Ok(()) => actions.push(change.to_string()),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+        None if revert_kill_switch => match sys.revert_kill_switch(ifname) {
+            Ok(()) => actions.push("reverted kill switch".to_string()),
+            Err(e) => errors.push(e),
+        },
+        None => {}
+    }
+    if sys.read_peers(ifname).is_ok() {
+        match sys.remove_interface(ifname) {
+            Ok(()) => actions.push(format!("removed interface {ifname}")),
+            Err(e) => errors.push(e),
+        }
+    }
+    let _ = fs::remove_file(state_path(ifname));
+    let _ = fs::remove_file(crate::domains::table_path(ifname));
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(actions),
+    }
+}
+
+/**
+ * @brief Make an interface ready for a new owner, cleaning up after a crashed run when that is safe.
+ * @param sys System layer to act on.
+ * @param ifname Interface name.
+ * @param force Also clean up an orphaned interface that no state file describes.
+ * @param revert_kill_switch Passed to `recover` for the no-state-file case.
+ * @return Cleanup actions taken; empty when nothing was left over.
+ */
+pub fn clear_leftovers(sys: &dyn System, ifname: &str, force: bool, revert_kill_switch: bool) -> Result<Vec<String>, VpnError> {
+    let left = detect_leftovers(sys, ifname);
+    if left.owner_alive {
+        let pid = left.state.as_ref().map(|s| s.pid);
+        return Err(VpnError::AlreadyRunning { ifname: ifname.to_string(), pid });
+    }
+    if !left.is_stale() { return Ok(Vec::new()); }
+    if !left.is_recoverable_automatically() && !force {
+        return Err(VpnError::SystemCommand(format!(
+            "interface {ifname} already exists but no runtime state describes it; run `vpn-client recover` or pass --force"
+        )));
+    }
+    recover(sys, ifname, revert_kill_switch)
+}
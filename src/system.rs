@@ -0,0 +1,93 @@
+use crate::dns::{self, DnsSnapshot};
+use crate::error::VpnError;
+use crate::route::{self, DefaultRoute};
+use crate::health::{self, Probe};
+use crate::http::{self, HttpResponse};
+use crate::browser;
+use crate::kill_switch;
+use defguard_wireguard_rs::{host::Peer, InterfaceConfiguration, Kernel, WGApi, WireguardInterfaceApi};
+use std::{net::IpAddr, time::Duration};
+
+/**
+ * @brief Every host change a tunnel makes, behind one interface.
+ *
+ * `HostSystem` drives the real kernel, firewall, routes and resolver; tests substitute a
+ * fake to observe calls and inject failures.
+ */
+pub trait System: Send + Sync {
+    fn create_interface(&self, ifname: &str) -> Result<(), VpnError>;
+    fn configure_interface(&self, config: &InterfaceConfiguration) -> Result<(), VpnError>;
+    fn remove_interface(&self, ifname: &str) -> Result<(), VpnError>;
+    fn configure_peer(&self, ifname: &str, peer: &Peer) -> Result<(), VpnError>;
+    fn read_peers(&self, ifname: &str) -> Result<Vec<Peer>, VpnError>;
+    fn apply_kill_switch(&self, ifname: &str) -> Result<(), VpnError>;
+    fn revert_kill_switch(&self, ifname: &str) -> Result<(), VpnError>;
+    fn snapshot_default_route(&self) -> Option<DefaultRoute>;
+    fn route_to(&self, ip: &str) -> Option<String>;
+    fn add_host_route(&self, endpoint_ip: &str, via: &DefaultRoute) -> Result<(), VpnError>;
+    fn remove_host_route(&self, endpoint_ip: &str) -> Result<(), VpnError>;
+    fn add_tunnel_route(&self, addr: &IpAddr, ifname: &str) -> Result<(), VpnError>;
+    fn del_tunnel_route(&self, addr: &IpAddr, ifname: &str) -> Result<(), VpnError>;
+    fn snapshot_dns(&self, ifname: &str) -> DnsSnapshot;
+    fn apply_dns(&self, ifname: &str, servers: &[String]) -> Result<(), VpnError>;
+    fn restore_dns(&self, snapshot: &DnsSnapshot) -> Result<(), VpnError>;
+    fn probe_connectivity(&self, probe: &Probe, timeout: Duration) -> Result<(), VpnError>;
+    fn http_get(&self, url: &str, timeout: Duration) -> Result<HttpResponse, VpnError>;
+    fn open_url(&self, url: &str);
+}
+
+/** @brief The real host: kernel WireGuard plus the `kill_switch`, `route`, `dns`, `http` and `browser` modules. */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HostSystem;
+
+impl System for HostSystem {
+    fn create_interface(&self, ifname: &str) -> Result<(), VpnError> {
+        WGApi::<Kernel>::new(ifname.to_string())?.create_interface()?;
+        Ok(())
+    }
+
+    fn configure_interface(&self, config: &InterfaceConfiguration) -> Result<(), VpnError> {
+        let wgapi = WGApi::<Kernel>::new(config.name.clone())?;
+        #[cfg(target_os = "windows")]
+        {
+            wgapi.configure_interface(config, &[], &[])?;
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            wgapi.configure_interface(config)?;
+        }
+        Ok(())
+    }
+
+    fn remove_interface(&self, ifname: &str) -> Result<(), VpnError> {
+        let _ = std::process::Command::new("ip").args(["link", "set", ifname, "down"]).output();
+        WGApi::<Kernel>::new(ifname.to_string())?.remove_interface()?;
+        Ok(())
+    }
+
+    fn configure_peer(&self, ifname: &str, peer: &Peer) -> Result<(), VpnError> {
+        WGApi::<Kernel>::new(ifname.to_string())?.configure_peer(peer)?;
+        Ok(())
+    }
+
+    fn read_peers(&self, ifname: &str) -> Result<Vec<Peer>, VpnError> {
+        let data = WGApi::<Kernel>::new(ifname.to_string())?.read_interface_data()?;
+        Ok(data.peers.into_values().collect())
+    }
+
+    fn apply_kill_switch(&self, ifname: &str) -> Result<(), VpnError> { kill_switch::apply_kill_switch(ifname) }
+    fn revert_kill_switch(&self, ifname: &str) -> Result<(), VpnError> { kill_switch::revert_kill_switch(ifname) }
+    fn snapshot_default_route(&self) -> Option<DefaultRoute> { route::snapshot_default() }
+    fn route_to(&self, ip: &str) -> Option<String> { route::route_to(ip) }
+    fn add_host_route(&self, endpoint_ip: &str, via: &DefaultRoute) -> Result<(), VpnError> { route::host_route_to_endpoint(endpoint_ip, via) }
+    fn remove_host_route(&self, endpoint_ip: &str) -> Result<(), VpnError> { route::remove_host_route_to_endpoint(endpoint_ip) }
+    fn add_tunnel_route(&self, addr: &IpAddr, ifname: &str) -> Result<(), VpnError> { route::add_tunnel_route(addr, ifname) }
+    fn del_tunnel_route(&self, addr: &IpAddr, ifname: &str) -> Result<(), VpnError> { route::del_tunnel_route(addr, ifname) }
+    fn snapshot_dns(&self, ifname: &str) -> DnsSnapshot { dns::snapshot_dns(ifname) }
+    fn apply_dns(&self, ifname: &str, servers: &[String]) -> Result<(), VpnError> { dns::apply_full_tunnel_dns(ifname, servers) }
+    fn restore_dns(&self, snapshot: &DnsSnapshot) -> Result<(), VpnError> { dns::restore_dns(snapshot) }
+
+    fn probe_connectivity(&self, probe: &Probe, timeout: Duration) -> Result<(), VpnError> { health::run_probe(probe, timeout) }
+    fn http_get(&self, url: &str, timeout: Duration) -> Result<HttpResponse, VpnError> { http::get(url, timeout) }
+    fn open_url(&self, url: &str) { browser::open_url(url) }
+}
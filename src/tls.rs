@@ -0,0 +1,130 @@
+use crate::error::VpnError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::{fs::File, io::BufReader, path::PathBuf, sync::Arc};
+
+/** @brief How HTTPS servers are authenticated. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSettings {
+    /** PEM bundle trusted instead of the built-in Mozilla roots. */
+    pub ca_file: Option<PathBuf>,
+    /** SHA-256 of the server certificate's SubjectPublicKeyInfo, checked after the chain is verified. */
+    pub spki_pin: Option<[u8; 32]>,
+}
+
+impl TlsSettings {
+    /**
+     * @brief Build settings from the `enroll_ca_file` and `enroll_spki_pin` config values.
+     * @param ca_file Path to a PEM CA bundle.
+     * @param pin Pin in `sha256/<base64>` or bare base64 form.
+     */
+    pub fn from_config(ca_file: Option<&str>, pin: Option<&str>) -> Result<Self, VpnError> {
+        let spki_pin = pin.map(parse_pin).transpose().map_err(|m| VpnError::Connectivity(format!("enroll_spki_pin {m}")))?;
+        Ok(Self { ca_file: ca_file.map(PathBuf::from), spki_pin })
+    }
+
+    /** @brief rustls client configuration enforcing these settings. */
+    pub fn client_config(&self) -> Result<Arc<rustls::ClientConfig>, VpnError> {
+        let provider = Arc::new(ring::default_provider());
+        let roots = Arc::new(self.root_store()?);
+        let inner = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+            .build()
+            .map_err(|e| VpnError::Connectivity(format!("TLS verifier: {e}")))?;
+        let verifier = Arc::new(PinnedVerifier { inner, pin: self.spki_pin, provider: provider.clone() });
+        let cfg = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| VpnError::Connectivity(format!("TLS setup: {e}")))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth();
+        Ok(Arc::new(cfg))
+    }
+
+    fn root_store(&self) -> Result<RootCertStore, VpnError> {
+        let Some(path) = &self.ca_file else {
+            return Ok(RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() });
+        };
+        let bad = |m: String| VpnError::Connectivity(format!("CA bundle {}: {m}", path.display()));
+        let mut reader = BufReader::new(File::open(path).map_err(|e| bad(e.to_string()))?);
+        let mut store = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut reader) {
+            store.add(cert.map_err(|e| bad(e.to_string()))?).map_err(|e| bad(e.to_string()))?;
+        }
+        if store.is_empty() {
+            return Err(bad("no certificates found".into()));
+        }
+        Ok(store)
+    }
+}
+
+/**
+ * @brief Decode an SPKI pin.
+ * @param pin `sha256/<base64>` or bare base64 of a 32-byte SHA-256 digest.
+ * @return The digest, or a message describing the expected format.
+ */
+pub fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    let b64 = pin.trim().strip_prefix("sha256/").unwrap_or(pin.trim());
+    STANDARD.decode(b64).ok()
+        .and_then(|d| <[u8; 32]>::try_from(d).ok())
+        .ok_or_else(|| "must be `sha256/` followed by the base64 SHA-256 of the server's SubjectPublicKeyInfo".to_string())
+}
+
+/**
+ * @brief Pin of a DER certificate, in `sha256/<base64>` form.
+ * @param cert_der Certificate to hash.
+ */
+pub fn spki_pin_of(cert_der: &[u8]) -> Result<String, VpnError> {
+    Ok(format!("sha256/{}", STANDARD.encode(spki_sha256(&CertificateDer::from(cert_der))?)))
+}
+
+fn spki_sha256(cert: &CertificateDer<'_>) -> Result<[u8; 32], VpnError> {
+    let ee = webpki::EndEntityCert::try_from(cert)
+        .map_err(|e| VpnError::Connectivity(format!("bad certificate: {e}")))?;
+    Ok(Sha256::digest(ee.subject_public_key_info().as_ref()).into())
+}
+
+/** Standard WebPKI verification, followed by the optional SPKI pin check. */
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pin: Option<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        if let Some(pin) = self.pin {
+            let got = spki_sha256(end_entity).map_err(|e| rustls::Error::General(e.to_string()))?;
+            if got != pin {
+                return Err(rustls::Error::General(format!(
+                    "certificate key does not match enroll_spki_pin (server presented sha256/{})", STANDARD.encode(got))));
+            }
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
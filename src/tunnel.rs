@@ -0,0 +1,882 @@
+use crate::auth;
+use crate::captive::{classify, PortalCheck};
+use crate::config::{derive_public_key_b64, ClientConfig};
+use crate::domains::{self, DomainRoute, DomainTable};
+use crate::enroll::{enroll, enroll_and_apply, EnrollOptions};
+use crate::error::VpnError;
+use crate::health::run_health_check;
+use crate::journal::{undo, Change, Journal};
+use crate::netwatch::{NetEvent, NetWatcher};
+use crate::route::DefaultRoute;
+use crate::system::{HostSystem, System};
+use crate::filelog::LogSink;
+use crate::{browser, build_interface_config};
+use defguard_wireguard_rs::{host::Peer, net::IpAddrMask};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::mpsc::{self, Receiver, Sender},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/** @brief A handshake older than this means the tunnel is dead; WireGuard re-handshakes every 2 minutes while in use. */
+pub const STALE_HANDSHAKE_SECS: u64 = 180;
+/** @brief Delay after the first reconnect attempt; doubles with every further attempt. */
+pub const BASE_BACKOFF_SECS: u64 = 5;
+/** @brief Upper bound on the delay between reconnect attempts. */
+pub const MAX_BACKOFF_SECS: u64 = 300;
+
+/** @brief How often the captive portal check is repeated while waiting for the user to log in. */
+pub const PORTAL_RECHECK_SECS: u64 = 2;
+/** @brief Time limit for one request to the captive portal check URL. */
+pub const PORTAL_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/** @brief Lifecycle of a tunnel session. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TunnelState {
+    Idle,
+    Preflight,
+    Enrolling,
+    Configuring,
+    Handshaking,
+    Verifying,
+    Connected,
+    Reconnecting,
+    TearingDown,
+    Failed,
+}
+
+impl fmt::Display for TunnelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/** @brief State transition delivered to subscribers. */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelEvent {
+    pub from: TunnelState,
+    pub to: TunnelState,
+    pub detail: String,
+}
+
+/** @brief Snapshot of a tunnel's state and peer counters. */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelStatus {
+    pub ifname: String,
+    pub state: TunnelState,
+    pub last_handshake: Option<SystemTime>,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    pub domains: Vec<DomainRoute>,
+}
+
+impl TunnelStatus {
+    /**
+     * @brief Time since the last handshake.
+     * @param now Reference time, normally `SystemTime::now()`.
+     */
+    pub fn handshake_age(&self, now: SystemTime) -> Option<Duration> {
+        self.last_handshake.map(|t| now.duration_since(t).unwrap_or_default())
+    }
+
+    /** @brief True when no handshake has been seen within `STALE_HANDSHAKE_SECS`. */
+    pub fn handshake_stale(&self, now: SystemTime) -> bool {
+        self.handshake_age(now).is_none_or(|age| age.as_secs() > STALE_HANDSHAKE_SECS)
+    }
+}
+
+/**
+ * @brief Delay before the next reconnect attempt.
+ * @param attempt Number of attempts already made, starting at 0.
+ */
+pub fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_secs(BASE_BACKOFF_SECS.saturating_mul(1 << attempt.min(16)).min(MAX_BACKOFF_SECS))
+}
+
+/**
+ * @brief A client VPN session: enrollment, interface, kill switch, routes, DNS and monitoring.
+ *
+ * `up` walks Idle → (Preflight) → Enrolling → Configuring → Handshaking → Verifying → Connected. Every
+ * host change is recorded in a `Journal`, so a failed step, a panic or a stop request
+ * mid-setup rolls back everything applied so far; `down` unwinds it in an orderly way.
+ * While connected, `poll` watches the handshake age and the `[health_check]` probes and
+ * moves to Reconnecting when either fails, cycling through the configured endpoints with exponential backoff. With
+ * `watch_network` it also follows default route changes (e.g. Wi-Fi to Ethernet).
+ */
+pub struct Tunnel {
+    cfg: ClientConfig,
+    ifname: String,
+    state: TunnelState,
+    subscribers: Vec<Sender<TunnelEvent>>,
+    sys: Arc<dyn System>,
+    journal: Journal,
+    stop: Arc<AtomicBool>,
+    base_peer: Option<Peer>,
+    endpoint_ip: String,
+    domain_table: DomainTable,
+    reconnect_attempt: u32,
+    next_reconnect: Option<Instant>,
+    default_route: Option<DefaultRoute>,
+    netwatch: Option<NetWatcher>,
+    last_health: Option<Instant>,
+    health_failed: bool,
+    log: LogSink,
+}
+
+impl Tunnel {
+    /**
+     * @brief Create an idle session for a config that already has a client key.
+     *
+     * Applied changes are mirrored to the runtime state file so `disconnect` can undo them from another process.
+     * @param cfg Client configuration.
+     * @param ifname Interface override; defaults to `cfg.interface_name`.
+     */
+    pub fn new(cfg: ClientConfig, ifname: Option<String>) -> Self {
+        let mut t = Self::with_system(cfg, ifname, Arc::new(HostSystem));
+        let path = crate::state::state_path(&t.ifname);
+        t.journal.persist_to(&t.ifname, path);
+        t
+    }
+
+    /**
+     * @brief Create an idle session that applies its changes through `sys`.
+     * @param cfg Client configuration.
+     * @param ifname Interface override; defaults to `cfg.interface_name`.
+     * @param sys System layer (the real host or a test double).
+     */
+    pub fn with_system(cfg: ClientConfig, ifname: Option<String>, sys: Arc<dyn System>) -> Self {
+        let ifname = ifname.unwrap_or_else(|| cfg.interface_name.clone());
+        let endpoint_ip = endpoint_host(&cfg.server_endpoint);
+        Self {
+            cfg,
+            ifname,
+            state: TunnelState::Idle,
+            subscribers: Vec::new(),
+            journal: Journal::new(sys.clone()),
+            sys,
+            stop: Arc::new(AtomicBool::new(false)),
+            base_peer: None,
+            endpoint_ip,
+            domain_table: DomainTable::default(),
+            reconnect_attempt: 0,
+            next_reconnect: None,
+            default_route: None,
+            netwatch: None,
+            last_health: None,
+            health_failed: false,
+            log: LogSink::default(),
+        }
+    }
+
+    /** @brief Log progress and best-effort failures (which never reach the caller) to `log`. */
+    pub fn log_to(&mut self, log: LogSink) {
+        self.journal.log_to(log.clone());
+        self.log = log;
+    }
+
+    /** @brief Receive every state change from now on. */
+    pub fn subscribe(&mut self) -> Receiver<TunnelEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /**
+     * @brief Flag that requests a stop; setting it during `up` aborts setup and rolls back.
+     * @return Shared flag, typically set from a Ctrl+C handler.
+     */
+    pub fn stop_handle(&self) -> Arc<AtomicBool> { self.stop.clone() }
+
+    /** @brief True once a stop has been requested through `stop_handle`. */
+    pub fn stop_requested(&self) -> bool { self.stop.load(Ordering::SeqCst) }
+
+    /** @brief Current state. */
+    pub fn state(&self) -> TunnelState { self.state }
+
+    /** @brief Interface name this session manages. */
+    pub fn ifname(&self) -> &str { &self.ifname }
+
+    /** @brief Current configuration, including any settings assigned at enrollment. */
+    pub fn config(&self) -> &ClientConfig { &self.cfg }
+
+    /** @brief Host changes currently in effect, oldest first. */
+    pub fn applied_changes(&self) -> &[Change] { self.journal.changes() }
+
+    /**
+     * @brief Bring the tunnel up and verify connectivity.
+     * @return Ok once Connected; on error every applied change is rolled back and the session is left in Failed.
+     */
+    pub fn up(&mut self) -> Result<(), VpnError> {
+        match self.try_up() {
+            Ok(()) => {
+                self.set_state(TunnelState::Connected, "connectivity verified");
+                self.log.line(&format!("Client connected on {}", self.ifname));
+                Ok(())
+            }
+            Err(e) => {
+                self.log.line(&format!("Connect failed on {}: {e}", self.ifname));
+                self.teardown();
+                self.set_state(TunnelState::Failed, &e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /**
+     * @brief Tear the tunnel down and restore the previous network configuration.
+     */
+    pub fn down(&mut self) -> Result<(), VpnError> {
+        if self.journal.is_empty() { return Ok(()); }
+        self.teardown();
+        self.set_state(TunnelState::Idle, "stopped");
+        self.log.line(&format!("Client stopped on {}", self.ifname));
+        Ok(())
+    }
+
+    /**
+     * @brief Follow network changes from rtnetlink; they are handled on every `poll`.
+     * @return Error when the netlink socket cannot be opened (e.g. not Linux).
+     */
+    pub fn watch_network(&mut self) -> Result<(), VpnError> {
+        self.netwatch = Some(NetWatcher::open()?);
+        Ok(())
+    }
+
+    /**
+     * @brief React to link, address or route changes.
+     *
+     * When the default route of the underlying network has moved, the endpoint bypass
+     * route is replaced through the new gateway, tunnel DNS is re-applied and the peer is
+     * re-set so WireGuard handshakes over the new path.
+     * @param events Changes reported since the last call (from `NetWatcher` or synthetic).
+     * @return True when the default route changed and the tunnel was adjusted.
+     */
+    pub fn handle_network_events(&mut self, events: &[NetEvent]) -> bool {
+        if events.is_empty() || !matches!(self.state, TunnelState::Connected | TunnelState::Reconnecting) {
+            return false;
+        }
+        let Some(current) = self.sys.snapshot_default_route() else { return false };
+        if current.device == self.ifname || self.default_route.as_ref() == Some(&current) {
+            return false;
+        }
+        self.log.line(&format!(
+            "Default route changed on {}: {:?} -> via {} dev {}", self.ifname, self.default_route, current.gateway, current.device
+        ));
+        self.default_route = Some(current);
+        if !self.cfg.split_tunnel {
+            self.reroute_endpoint(self.endpoint_ip.clone());
+            if self.journal.changes().iter().any(|c| matches!(c, Change::Dns { .. })) {
+                self.log_failure("Re-applying tunnel DNS", self.sys.apply_dns(&self.ifname, &self.cfg.effective_dns_servers()));
+            }
+        }
+        if let Some(peer) = self.current_peer() {
+            self.log_failure("Re-setting peer after network change", self.sys.configure_peer(&self.ifname, &peer));
+        }
+        true
+    }
+
+    /**
+     * @brief Switch a running tunnel to a rotated key without tearing it down.
+     *
+     * The interface is reconfigured in place; the kill switch, routes and DNS stay, and the
+     * next handshake uses the new key.
+     * @param cfg Config with the new private key and any settings the server reassigned.
+     * @return Error when the tunnel is not up or the interface rejects the new configuration.
+     */
+    pub fn swap_key(&mut self, cfg: ClientConfig) -> Result<(), VpnError> {
+        if !matches!(self.state, TunnelState::Connected | TunnelState::Reconnecting) {
+            return Err(VpnError::SystemCommand(format!("{} is not connected", self.ifname)));
+        }
+        let mut config = build_interface_config(&cfg, &self.ifname)?;
+        let configured = self.sys.configure_interface(&config);
+        config.prvkey.zeroize();
+        configured?;
+        self.base_peer = config.peers.first().cloned();
+        let endpoint_ip = endpoint_host(&cfg.server_endpoint);
+        self.cfg = cfg;
+        if !self.cfg.split_tunnel && endpoint_ip != self.endpoint_ip {
+            self.reroute_endpoint(endpoint_ip);
+        }
+        if !self.domain_table.addresses().is_empty() {
+            if let Some(peer) = self.current_peer() { self.sys.configure_peer(&self.ifname, &peer)?; }
+        }
+        self.log.line(&format!("Switched {} to the rotated client key", self.ifname));
+        Ok(())
+    }
+
+    /**
+     * @brief Periodic maintenance while connected: handle network changes, check the handshake,
+     *        reconnect when it is stale, refresh tunnel domains and report counters.
+     */
+    pub fn poll(&mut self) -> TunnelStatus {
+        let events = self.netwatch.as_ref().map(NetWatcher::drain).unwrap_or_default();
+        self.handle_network_events(&events);
+        if matches!(self.state, TunnelState::Connected | TunnelState::Reconnecting) {
+            self.check_handshake();
+        }
+        if self.state == TunnelState::Connected {
+            self.sync_tunnel_domains();
+        }
+        self.status()
+    }
+
+    /** @brief Current state together with live peer counters. */
+    pub fn status(&self) -> TunnelStatus {
+        let mut st = status_from_peers(&self.ifname, self.sys.read_peers(&self.ifname).ok());
+        st.state = self.state;
+        st.domains = self.domain_table.entries.clone();
+        st
+    }
+
+    fn check_stop(&self) -> Result<(), VpnError> {
+        if self.stop_requested() { Err(VpnError::Interrupted) } else { Ok(()) }
+    }
+
+    fn try_up(&mut self) -> Result<(), VpnError> {
+        if let Some(url) = self.cfg.captive_portal.check_url.clone() {
+            self.set_state(TunnelState::Preflight, "");
+            self.wait_for_open_network(&url)?;
+        }
+        self.set_state(TunnelState::Enrolling, "");
+        if self.cfg.client_private_key_b64.is_none() {
+            return Err(VpnError::InvalidKey("Missing client private key".into()));
+        }
+        if self.cfg.enroll_url.is_some() {
+            let enroll_opts = self.enroll_options()?;
+            match enroll_and_apply(&mut self.cfg, &enroll_opts) {
+                Ok(true) => {
+                    self.endpoint_ip = endpoint_host(&self.cfg.server_endpoint);
+                    self.log.line(&format!(
+                        "Enrollment assigned {} via {}", self.cfg.address_cidr, self.cfg.server_endpoint
+                    ));
+                }
+                Ok(false) => {}
+                // registered earlier (e.g. with a one-time invite): keep the stored settings
+                Err(VpnError::KeyAlreadyEnrolled(m)) => self.log.line(&format!("Already enrolled: {m}")),
+                Err(e) => return Err(e),
+            }
+        }
+        self.check_stop()?;
+
+        self.set_state(TunnelState::Configuring, "");
+        let mut config = build_interface_config(&self.cfg, &self.ifname)?;
+        self.base_peer = config.peers.first().cloned();
+        self.sys.create_interface(&self.ifname)?;
+        self.journal.record(Change::Interface { ifname: self.ifname.clone() });
+        let configured = self.sys.configure_interface(&config);
+        config.prvkey.zeroize();
+        configured?;
+        self.default_route = self.sys.snapshot_default_route();
+        self.check_stop()?;
+        if self.cfg.kill_switch {
+            // Recorded first: a partially applied kill switch must still be reverted.
+            self.journal.record(Change::KillSwitch { ifname: self.ifname.clone() });
+            self.sys.apply_kill_switch(&self.ifname)?;
+            self.check_stop()?;
+        }
+        if !self.cfg.split_tunnel {
+            self.reroute_endpoint(self.endpoint_ip.clone());
+            self.check_stop()?;
+            // Recorded first, like the kill switch: a partially applied DNS change must still be restored.
+            self.journal.record(Change::Dns { snapshot: self.sys.snapshot_dns(&self.ifname) });
+            if let Err(e) = self.sys.apply_dns(&self.ifname, &self.cfg.effective_dns_servers()) {
+                self.log_failure("Tunnel DNS", Err(e));
+            }
+            self.check_stop()?;
+        }
+
+        self.set_state(TunnelState::Handshaking, "");
+        self.wait_for_handshake()?;
+
+        self.set_state(TunnelState::Verifying, "");
+        self.run_health()?;
+        self.check_stop()?;
+        self.sync_tunnel_domains();
+        Ok(())
+    }
+
+    /**
+     * Check for a captive portal before touching the host. When one is found, open it in
+     * the browser and wait until the check URL answers normally or the login times out.
+     * An unreachable check URL is not a portal: setup continues and the handshake decides.
+     */
+    fn wait_for_open_network(&mut self, url: &str) -> Result<(), VpnError> {
+        let cp = self.cfg.captive_portal.clone();
+        let timeout = Duration::from_secs(PORTAL_CHECK_TIMEOUT_SECS);
+        let check = |sys: &dyn System| sys.http_get(url, timeout).map(|r| classify(&cp, &r));
+        let login_url = match check(self.sys.as_ref()) {
+            Ok(PortalCheck::Open) => return Ok(()),
+            Ok(PortalCheck::Portal { login_url }) => login_url,
+            Err(e) => {
+                self.log_failure("Captive portal check", Err(e));
+                return Ok(());
+            }
+        };
+        // The redirect target comes from the untrusted network; the check URL leads there too.
+        let login_url = match browser::check_url(&login_url) {
+            Ok(()) => login_url,
+            Err(e) => {
+                self.log_failure("Captive portal login URL", Err(e));
+                url.to_string()
+            }
+        };
+        self.log.line(&format!("Captive portal detected, opening {login_url}"));
+        self.set_state(TunnelState::Preflight, &format!("Captive portal detected — log in at {login_url} to continue"));
+        self.sys.open_url(&login_url);
+        let deadline = Instant::now() + Duration::from_secs(cp.login_timeout_secs);
+        while Instant::now() < deadline {
+            self.check_stop()?;
+            thread::sleep(Duration::from_secs(PORTAL_RECHECK_SECS));
+            if let Ok(PortalCheck::Open) = check(self.sys.as_ref()) {
+                self.set_state(TunnelState::Preflight, "Captive portal login complete");
+                return Ok(());
+            }
+        }
+        Err(VpnError::Connectivity(format!("captive portal login not completed within {}s", cp.login_timeout_secs)))
+    }
+
+    /** Wait for the first handshake, re-setting the peer and backing off between attempts. */
+    fn wait_for_handshake(&mut self) -> Result<(), VpnError> {
+        let timeout = Duration::from_secs(self.cfg.handshake_timeout_secs);
+        let poll = Duration::from_millis(self.cfg.handshake_poll_ms.max(10));
+        let attempts = self.cfg.handshake_retries + 1;
+        for attempt in 1..=attempts {
+            let start = Instant::now();
+            while start.elapsed() < timeout {
+                if self.handshake_seen() { return Ok(()); }
+                self.check_stop()?;
+                thread::sleep(poll);
+            }
+            if self.handshake_seen() { return Ok(()); }
+            if attempt == attempts { break; }
+            let pause = Duration::from_secs(self.cfg.retry_backoff_secs.saturating_mul(1 << (attempt - 1).min(16)));
+            self.log.line(&format!(
+                "No handshake on {} after {}s (attempt {attempt}/{attempts}), retrying in {}s", self.ifname, timeout.as_secs(), pause.as_secs()
+            ));
+            let resume = Instant::now() + pause;
+            while Instant::now() < resume {
+                self.check_stop()?;
+                thread::sleep(poll.min(resume - Instant::now()));
+            }
+            if let Some(peer) = self.current_peer() {
+                self.sys.configure_peer(&self.ifname, &peer)?;
+            }
+            self.set_state(TunnelState::Handshaking, &format!("retry {attempt}/{}", attempts - 1));
+        }
+        Err(VpnError::HandshakeTimeout(format!(
+            "no handshake on {} within {}s after {attempts} attempt(s) — server unreachable ({})",
+            self.ifname, timeout.as_secs(), self.handshake_diagnostics()
+        )))
+    }
+
+    /** Counters, endpoint route and kill switch state for a handshake timeout message. */
+    fn handshake_diagnostics(&self) -> String {
+        let st = self.status();
+        let traffic = match (st.tx_bytes, st.rx_bytes) {
+            (0, _) => "nothing sent: the interface never transmitted",
+            (_, 0) => "packets sent but nothing received: server down, wrong key or UDP blocked",
+            _ => "packets sent and received but no handshake completed",
+        };
+        let route = self.sys.route_to(&self.endpoint_ip).unwrap_or_else(|| "none".to_string());
+        let kill_switch = if self.journal.changes().iter().any(|c| matches!(c, Change::KillSwitch { .. })) { "on" } else { "off" };
+        format!("tx {} B, rx {} B, {traffic}; endpoint route: {route}; kill switch: {kill_switch}", st.tx_bytes, st.rx_bytes)
+    }
+
+    fn handshake_seen(&self) -> bool {
+        self.sys.read_peers(&self.ifname)
+            .map(|peers| peers.iter().any(|p| p.last_handshake.is_some()))
+            .unwrap_or(false)
+    }
+
+    /** Move between Connected and Reconnecting on handshake age and health checks, and run due reconnect attempts. */
+    fn check_handshake(&mut self) {
+        let now = SystemTime::now();
+        let st = self.status();
+        let stale = st.handshake_stale(now);
+        if self.state == TunnelState::Connected {
+            let failure = if stale {
+                Some(match st.handshake_age(now) {
+                    Some(age) => format!("last handshake {}s ago", age.as_secs()),
+                    None => "no handshake".to_string(),
+                })
+            } else if self.health_due() {
+                self.run_health().err().map(|e| format!("health check failed: {e}"))
+            } else {
+                None
+            };
+            if let Some(detail) = failure {
+                self.health_failed = !stale;
+                self.log.line(&format!("Tunnel unhealthy on {}: {detail}", self.ifname));
+                self.set_state(TunnelState::Reconnecting, &detail);
+                self.reconnect_attempt = 0;
+                self.next_reconnect = None;
+            }
+        } else if self.state == TunnelState::Reconnecting && !stale && !self.health_failed {
+            self.restored(&format!("handshake restored after {} attempt(s)", self.reconnect_attempt));
+        }
+        if self.state == TunnelState::Reconnecting && self.next_reconnect.is_none_or(|t| Instant::now() >= t) {
+            if self.health_failed && !stale && self.run_health().is_ok() {
+                self.restored(&format!("health check passed after {} attempt(s)", self.reconnect_attempt));
+            } else {
+                self.reconnect_once();
+            }
+        }
+    }
+
+    /** Enrollment settings, carrying a fresh SSO token when `[auth]` is configured. */
+    fn enroll_options(&self) -> Result<EnrollOptions, VpnError> {
+        let mut opts = EnrollOptions::from_config(&self.cfg)?;
+        if let Some(auth_cfg) = &self.cfg.auth {
+            let token = auth::current_token(auth_cfg, &self.ifname, &auth::tls_settings(&self.cfg)?)?
+                .ok_or_else(|| VpnError::Auth("not logged in; run `vpn-client login`".into()))?;
+            opts.token = Some(token.access_token);
+        }
+        Ok(opts)
+    }
+
+    /** Refresh the SSO token and re-enroll with it, so the server can revoke access; failures are only logged. */
+    fn renew_enrollment(&self) {
+        let (Some(_), Some(url), Some(key)) = (&self.cfg.auth, &self.cfg.enroll_url, &self.cfg.client_private_key_b64) else { return };
+        let res = self.enroll_options()
+            .and_then(|opts| enroll(url, &derive_public_key_b64(key.expose())?, &opts));
+        match res {
+            Ok(_) | Err(VpnError::KeyAlreadyEnrolled(_)) => {}
+            Err(e) => self.log.line(&format!("Renewing enrollment on {} failed: {e}", self.ifname)),
+        }
+    }
+
+    fn restored(&mut self, detail: &str) {
+        self.log.line(&format!("Reconnected on {}: {detail}", self.ifname));
+        self.set_state(TunnelState::Connected, detail);
+        self.reconnect_attempt = 0;
+        self.next_reconnect = None;
+        self.health_failed = false;
+    }
+
+    fn health_due(&self) -> bool {
+        let interval = Duration::from_secs(self.cfg.health_check.interval_secs);
+        !interval.is_zero() && self.last_health.is_none_or(|t| t.elapsed() >= interval)
+    }
+
+    fn run_health(&mut self) -> Result<(), VpnError> {
+        self.last_health = Some(Instant::now());
+        // tunnel DNS is applied to full tunnels only
+        let tunnel_dns = if self.cfg.split_tunnel { Vec::new() } else { self.cfg.effective_dns_servers() };
+        run_health_check(self.sys.as_ref(), &self.cfg.health_check, self.cfg.split_tunnel, &tunnel_dns)
+    }
+
+    /** Re-resolve the next endpoint in rotation, point the peer at it and schedule the following attempt. */
+    fn reconnect_once(&mut self) {
+        if self.reconnect_attempt == 0 { self.renew_enrollment(); }
+        let candidates: Vec<String> = std::iter::once(self.cfg.server_endpoint.clone())
+            .chain(self.cfg.fallback_endpoints.iter().cloned())
+            .collect();
+        let endpoint = &candidates[self.reconnect_attempt as usize % candidates.len()];
+        let res = resolve_endpoint(endpoint).and_then(|addr| {
+            if let Some(peer) = self.base_peer.as_mut() { peer.endpoint = Some(addr); }
+            if !self.cfg.split_tunnel && addr.ip().to_string() != self.endpoint_ip {
+                self.reroute_endpoint(addr.ip().to_string());
+            }
+            match self.current_peer() {
+                Some(peer) => self.sys.configure_peer(&self.ifname, &peer).map(|_| addr),
+                None => Ok(addr),
+            }
+        });
+        let msg = match res {
+            Ok(addr) => format!("Reconnect attempt {} on {}: endpoint {endpoint} ({addr})", self.reconnect_attempt + 1, self.ifname),
+            Err(e) => format!("Reconnect attempt {} on {} via {endpoint} failed: {e}", self.reconnect_attempt + 1, self.ifname),
+        };
+        self.log.line(&msg);
+        self.next_reconnect = Some(Instant::now() + backoff_delay(self.reconnect_attempt));
+        self.reconnect_attempt += 1;
+    }
+
+    /** Point the endpoint bypass route at `ip` through the current default gateway. */
+    fn reroute_endpoint(&mut self, ip: String) {
+        let old = Change::HostRoute { endpoint_ip: self.endpoint_ip.clone() };
+        if self.journal.changes().contains(&old) {
+            let _ = undo(self.sys.as_ref(), &old);
+            self.journal.forget(&old);
+        }
+        self.endpoint_ip = ip;
+        self.default_route = self.sys.snapshot_default_route();
+        if let Some(via) = self.default_route.clone() {
+            match self.sys.add_host_route(&self.endpoint_ip, &via) {
+                Ok(()) => self.journal.record(Change::HostRoute { endpoint_ip: self.endpoint_ip.clone() }),
+                Err(e) => self.log_failure("Endpoint host route", Err(e)),
+            }
+        }
+    }
+
+    /** The server peer with tunnel domain addresses added to its allowed IPs. */
+    fn current_peer(&self) -> Option<Peer> {
+        let mut peer = self.base_peer.clone()?;
+        peer.allowed_ips.extend(self.domain_table.addresses().into_iter().map(IpAddrMask::host));
+        Some(peer)
+    }
+
+    /** Resolve `tunnel_domains` and push changed addresses into the peer's allowed IPs and the route table. */
+    fn sync_tunnel_domains(&mut self) {
+        if self.cfg.tunnel_domains.is_empty() { return; }
+        if self.base_peer.is_none() { return; }
+        let before = self.domain_table.clone();
+        let delta = self.domain_table.refresh(&self.cfg.tunnel_domains, domains::unix_now(), crate::resolver::resolve_with_ttl);
+        if delta.is_empty() {
+            // renewed TTLs only: keep the expiry times shown by `status` current
+            if self.domain_table != before { let _ = self.domain_table.save(&domains::table_path(&self.ifname)); }
+            return;
+        }
+        let Some(peer) = self.current_peer() else { return };
+        if let Err(e) = self.sys.configure_peer(&self.ifname, &peer) {
+            self.log.line(&format!("Failed to update allowed IPs for tunnel domains: {e}"));
+            return;
+        }
+        for addr in delta.added.iter().copied() {
+            match self.sys.add_tunnel_route(&addr, &self.ifname) {
+                Ok(()) => self.journal.record(Change::TunnelRoute { addr, ifname: self.ifname.clone() }),
+                Err(e) => self.log_failure("Tunnel domain route", Err(e)),
+            }
+        }
+        for addr in delta.removed.iter().copied() {
+            let change = Change::TunnelRoute { addr, ifname: self.ifname.clone() };
+            let _ = undo(self.sys.as_ref(), &change);
+            self.journal.forget(&change);
+        }
+        self.log.line(&format!("Tunnel domains: +{:?} -{:?}", delta.added, delta.removed));
+        let _ = self.domain_table.save(&domains::table_path(&self.ifname));
+    }
+
+    fn teardown(&mut self) {
+        self.set_state(TunnelState::TearingDown, "");
+        for e in self.journal.unwind() {
+            self.log_failure("Rollback", Err(e));
+        }
+        self.domain_table = DomainTable::default();
+        let _ = std::fs::remove_file(domains::table_path(&self.ifname));
+    }
+
+    fn set_state(&mut self, to: TunnelState, detail: &str) {
+        let from = self.state;
+        self.state = to;
+        let ev = TunnelEvent { from, to, detail: detail.to_string() };
+        self.subscribers.retain(|s| s.send(ev.clone()).is_ok());
+    }
+
+    /** Report a failed best-effort step in the log, then carry on. */
+    fn log_failure(&self, what: &str, res: Result<(), VpnError>) {
+        if let Err(e) = res {
+            self.log.line(&format!("{what}: {e}"));
+        }
+    }
+}
+
+/**
+ * @brief Read the state of an interface managed by another process or session.
+ * @param ifname Interface name.
+ * @return Connected when a handshake has been seen, Handshaking when the interface exists, Idle otherwise.
+ */
+pub fn read_status(ifname: &str) -> TunnelStatus {
+    status_from_system(&HostSystem, ifname)
+}
+
+/**
+ * @brief Like `read_status`, querying peers through `sys`.
+ * @param sys System layer to query.
+ * @param ifname Interface name.
+ */
+pub fn status_from_system(sys: &dyn System, ifname: &str) -> TunnelStatus {
+    status_from_peers(ifname, sys.read_peers(ifname).ok())
+}
+
+fn status_from_peers(ifname: &str, peers: Option<Vec<Peer>>) -> TunnelStatus {
+    let mut st = TunnelStatus {
+        ifname: ifname.to_string(),
+        state: TunnelState::Idle,
+        last_handshake: None,
+        tx_bytes: 0,
+        rx_bytes: 0,
+        domains: DomainTable::load(&domains::table_path(ifname)).map(|t| t.entries).unwrap_or_default(),
+    };
+    let Some(peers) = peers else { return st };
+    st.state = TunnelState::Handshaking;
+    for p in &peers {
+        st.tx_bytes += p.tx_bytes;
+        st.rx_bytes += p.rx_bytes;
+        if p.last_handshake.is_some() {
+            st.state = TunnelState::Connected;
+            st.last_handshake = st.last_handshake.max(p.last_handshake);
+        }
+    }
+    st
+}
+
+/** Resolve a `host:port` endpoint, preferring IPv4. */
+fn resolve_endpoint(endpoint: &str) -> Result<std::net::SocketAddr, VpnError> {
+    use std::net::ToSocketAddrs;
+    let addrs: Vec<_> = endpoint.to_socket_addrs()
+        .map_err(|e| VpnError::InvalidEndpoint(format!("{endpoint}: {e}")))?
+        .collect();
+    addrs.iter().find(|a| a.is_ipv4()).or(addrs.first()).copied()
+        .ok_or_else(|| VpnError::InvalidEndpoint(format!("{endpoint}: no addresses")))
+}
+
+/** Host part of a `host:port` endpoint, used until the endpoint is resolved. */
+fn endpoint_host(endpoint: &str) -> String {
+    endpoint.split(':').next().unwrap_or("127.0.0.1").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsSnapshot;
+    use crate::route::DefaultRoute;
+    use defguard_wireguard_rs::InterfaceConfiguration;
+    use std::collections::BTreeSet;
+    use std::net::IpAddr;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    const FATAL_STEPS: &[&str] = &["create_interface", "configure_interface", "apply_kill_switch", "probe_connectivity"];
+    const BEST_EFFORT_STEPS: &[&str] = &["add_host_route", "apply_dns"];
+
+    #[derive(Default)]
+    struct FakeSystem {
+        fail_at: Option<&'static str>,
+        panic_at: Option<&'static str>,
+        stop_at: Option<&'static str>,
+        stop: Mutex<Option<Arc<AtomicBool>>>,
+        applied: Mutex<BTreeSet<&'static str>>,
+    }
+
+    impl FakeSystem {
+        fn step(&self, name: &'static str) -> Result<(), VpnError> {
+            if self.panic_at == Some(name) { panic!("injected panic at {name}"); }
+            if self.stop_at == Some(name) {
+                if let Some(flag) = self.stop.lock().unwrap().as_ref() { flag.store(true, Ordering::SeqCst); }
+            }
+            if self.fail_at == Some(name) { return Err(VpnError::SystemCommand(format!("injected failure at {name}"))); }
+            Ok(())
+        }
+        fn set(&self, what: &'static str, on: bool) {
+            let mut applied = self.applied.lock().unwrap();
+            if on { applied.insert(what); } else { applied.remove(what); }
+        }
+        fn applied(&self) -> BTreeSet<&'static str> { self.applied.lock().unwrap().clone() }
+    }
+
+    impl System for FakeSystem {
+        fn create_interface(&self, _: &str) -> Result<(), VpnError> { self.step("create_interface")?; self.set("interface", true); Ok(()) }
+        fn configure_interface(&self, _: &InterfaceConfiguration) -> Result<(), VpnError> { self.step("configure_interface") }
+        fn remove_interface(&self, _: &str) -> Result<(), VpnError> { self.set("interface", false); Ok(()) }
+        fn configure_peer(&self, _: &str, _: &Peer) -> Result<(), VpnError> { Ok(()) }
+        fn read_peers(&self, _: &str) -> Result<Vec<Peer>, VpnError> {
+            Ok(vec![Peer { last_handshake: Some(SystemTime::now()), ..Peer::default() }])
+        }
+        // Partially applied before failing, like an iptables policy set without its ACCEPT rule.
+        fn apply_kill_switch(&self, _: &str) -> Result<(), VpnError> { self.set("kill_switch", true); self.step("apply_kill_switch") }
+        fn revert_kill_switch(&self, _: &str) -> Result<(), VpnError> { self.set("kill_switch", false); Ok(()) }
+        fn snapshot_default_route(&self) -> Option<DefaultRoute> {
+            Some(DefaultRoute { gateway: "192.168.1.1".into(), device: "eth0".into() })
+        }
+        fn route_to(&self, _: &str) -> Option<String> { None }
+        fn http_get(&self, url: &str, _: Duration) -> Result<crate::http::HttpResponse, VpnError> {
+            Err(VpnError::Connectivity(format!("{url} unreachable")))
+        }
+        fn open_url(&self, _: &str) {}
+        fn add_host_route(&self, _: &str, _: &DefaultRoute) -> Result<(), VpnError> { self.step("add_host_route")?; self.set("host_route", true); Ok(()) }
+        fn remove_host_route(&self, _: &str) -> Result<(), VpnError> { self.set("host_route", false); Ok(()) }
+        fn add_tunnel_route(&self, _: &IpAddr, _: &str) -> Result<(), VpnError> { Ok(()) }
+        fn del_tunnel_route(&self, _: &IpAddr, _: &str) -> Result<(), VpnError> { Ok(()) }
+        fn snapshot_dns(&self, ifname: &str) -> DnsSnapshot { DnsSnapshot { ifname: ifname.into(), resolv_conf: None } }
+        // Partially applied before failing, like resolvectl setting the servers but not the domain.
+        fn apply_dns(&self, _: &str, _: &[String]) -> Result<(), VpnError> { self.set("dns", true); self.step("apply_dns") }
+        fn restore_dns(&self, _: &DnsSnapshot) -> Result<(), VpnError> { self.set("dns", false); Ok(()) }
+        fn probe_connectivity(&self, _: &crate::health::Probe, _: Duration) -> Result<(), VpnError> { self.step("probe_connectivity") }
+    }
+
+    fn test_config() -> ClientConfig {
+        ClientConfig {
+            client_private_key_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into()),
+            server_public_key_b64: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into(),
+            enroll_url: None,
+            kill_switch: true,
+            split_tunnel: false,
+            ..ClientConfig::default()
+        }
+    }
+
+    fn tunnel_with(fake: &Arc<FakeSystem>) -> Tunnel {
+        let t = Tunnel::with_system(test_config(), Some("wg-test".into()), fake.clone());
+        *fake.stop.lock().unwrap() = Some(t.stop_handle());
+        t
+    }
+
+    #[test]
+    fn full_setup_applies_everything_and_down_reverts_it() {
+        let fake = Arc::new(FakeSystem::default());
+        let mut t = tunnel_with(&fake);
+        t.up().unwrap();
+        assert_eq!(t.state(), TunnelState::Connected);
+        assert_eq!(fake.applied(), BTreeSet::from(["dns", "host_route", "interface", "kill_switch"]));
+        t.down().unwrap();
+        assert!(fake.applied().is_empty());
+    }
+
+    #[test]
+    fn failure_at_fatal_step_rolls_back_everything() {
+        for step in FATAL_STEPS {
+            let fake = Arc::new(FakeSystem { fail_at: Some(step), ..FakeSystem::default() });
+            let mut t = tunnel_with(&fake);
+            assert!(t.up().is_err(), "{step} should abort setup");
+            assert_eq!(t.state(), TunnelState::Failed);
+            assert!(fake.applied().is_empty(), "{step} left {:?}", fake.applied());
+            assert!(t.applied_changes().is_empty());
+        }
+    }
+
+    #[test]
+    fn failure_at_best_effort_step_is_skipped() {
+        for step in BEST_EFFORT_STEPS {
+            let fake = Arc::new(FakeSystem { fail_at: Some(step), ..FakeSystem::default() });
+            let mut t = tunnel_with(&fake);
+            t.up().unwrap();
+            t.down().unwrap();
+            assert!(fake.applied().is_empty(), "{step} left {:?}", fake.applied());
+        }
+    }
+
+    #[test]
+    fn partially_applied_dns_is_restored() {
+        let fake = Arc::new(FakeSystem { fail_at: Some("apply_dns"), ..FakeSystem::default() });
+        let mut t = tunnel_with(&fake);
+        t.up().unwrap();
+        assert!(t.applied_changes().iter().any(|c| matches!(c, Change::Dns { .. })), "recorded before it is applied");
+        t.down().unwrap();
+        assert!(!fake.applied().contains("dns"));
+    }
+
+    #[test]
+    fn panic_at_any_step_rolls_back_on_unwind() {
+        for step in FATAL_STEPS.iter().chain(BEST_EFFORT_STEPS) {
+            let fake = Arc::new(FakeSystem { panic_at: Some(step), ..FakeSystem::default() });
+            let res = catch_unwind(AssertUnwindSafe(|| { let _ = tunnel_with(&fake).up(); }));
+            assert!(res.is_err());
+            assert!(fake.applied().is_empty(), "{step} left {:?}", fake.applied());
+        }
+    }
+
+    #[test]
+    fn stop_request_mid_setup_rolls_back() {
+        for step in FATAL_STEPS.iter().chain(BEST_EFFORT_STEPS).filter(|s| **s != "create_interface") {
+            let fake = Arc::new(FakeSystem { stop_at: Some(step), ..FakeSystem::default() });
+            let mut t = tunnel_with(&fake);
+            assert!(matches!(t.up(), Err(VpnError::Interrupted)), "{step}");
+            assert!(fake.applied().is_empty(), "{step} left {:?}", fake.applied());
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::config::ClientConfig;
+use crate::health::Probe;
+use base64::Engine as _;
+use defguard_wireguard_rs::net::IpAddrMask;
+use std::{fmt, str::FromStr};
+
+/** @brief Every key `ClientConfig` understands, as dotted TOML paths. */
+pub const KNOWN_KEYS: &[&str] = &[
+    "interface_name",
+    "address_cidr",
+    "server_endpoint",
+    "server_public_key_b64",
+    "keepalive_secs",
+    "split_tunnel",
+    "kill_switch",
+    "client_private_key_b64",
+    "private_key_file",
+    "client_key_created",
+    "key_rotation_days",
+    "enroll_url",
+    "welcome_url",
+    "enroll_ca_file",
+    "enroll_spki_pin",
+    "enroll_token",
+    "unenroll_url",
+    "pending_unenroll",
+    "tunnel_domains",
+    "fallback_endpoints",
+    "allowed_ips",
+    "dns_servers",
+    "handshake_timeout_secs",
+    "handshake_poll_ms",
+    "handshake_retries",
+    "retry_backoff_secs",
+    "health_check.probes",
+    "health_check.policy",
+    "health_check.timeout_secs",
+    "health_check.interval_secs",
+    "captive_portal.check_url",
+    "captive_portal.expect_status",
+    "captive_portal.expect_body",
+    "captive_portal.login_timeout_secs",
+    "auth.device_authorization_url",
+    "auth.token_url",
+    "auth.client_id",
+    "auth.scope",
+];
+
+/** @brief A single problem found in a config, tagged with its TOML field path. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { field: field.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/**
+ * @brief Check an interface name; it also names files under the runtime directory.
+ * @param name Interface name from the config or a daemon request.
+ */
+pub fn check_interface_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 15 || name == "." || name == ".." || name.chars().any(|c| c == '/' || c.is_whitespace()) {
+        return Err("must be 1-15 characters without '/' or whitespace, and not `.` or `..`".into());
+    }
+    Ok(())
+}
+
+/**
+ * @brief Check every field of a parsed config and collect all problems.
+ * @param cfg Parsed configuration.
+ * @return Issues in field order; empty when the config is usable.
+ *
+ * An empty `server_public_key_b64` is accepted because a fresh config has not been
+ * through `import` yet; `connect` still refuses to build an interface without it.
+ */
+pub fn validate_config(cfg: &ClientConfig) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    if let Err(m) = check_interface_name(&cfg.interface_name) {
+        issues.push(ConfigIssue::new("interface_name", m));
+    }
+    if !cfg.address_cidr.contains('/') || IpAddrMask::from_str(&cfg.address_cidr).is_err() {
+        issues.push(ConfigIssue::new("address_cidr", format!("`{}` is not a valid CIDR (e.g. 10.8.0.2/32)", cfg.address_cidr)));
+    }
+    if cfg.server_endpoint.parse::<std::net::SocketAddr>().is_err() {
+        issues.push(ConfigIssue::new("server_endpoint", format!("`{}` is not a valid ip:port", cfg.server_endpoint)));
+    }
+    if !cfg.server_public_key_b64.is_empty() {
+        if let Err(m) = check_key(&cfg.server_public_key_b64) {
+            issues.push(ConfigIssue::new("server_public_key_b64", m));
+        }
+    }
+    if let Some(k) = &cfg.client_private_key_b64 {
+        if let Err(m) = check_key(k.expose()) {
+            issues.push(ConfigIssue::new("client_private_key_b64", m));
+        }
+    }
+    if cfg.private_key_file.as_deref().is_some_and(|f| f.trim().is_empty()) {
+        issues.push(ConfigIssue::new("private_key_file", "must not be empty"));
+    }
+    if let Some(created) = &cfg.client_key_created {
+        if let Err(m) = crate::keys::parse_created(created) {
+            issues.push(ConfigIssue::new("client_key_created", m));
+        }
+    }
+    for (field, url) in [("enroll_url", &cfg.enroll_url), ("unenroll_url", &cfg.unenroll_url), ("welcome_url", &cfg.welcome_url)] {
+        if let Some(u) = url {
+            if let Err(m) = check_url(u) {
+                issues.push(ConfigIssue::new(field, m));
+            }
+        }
+    }
+    for (i, a) in cfg.allowed_ips.iter().enumerate() {
+        if !a.contains('/') || IpAddrMask::from_str(a).is_err() {
+            issues.push(ConfigIssue::new(format!("allowed_ips[{i}]"), format!("`{a}` is not a CIDR like 10.8.0.0/24")));
+        }
+    }
+    for (i, d) in cfg.dns_servers.iter().enumerate() {
+        if d.parse::<std::net::IpAddr>().is_err() {
+            issues.push(ConfigIssue::new(format!("dns_servers[{i}]"), format!("`{d}` is not an IP address")));
+        }
+    }
+    if let Some(auth) = &cfg.auth {
+        for (field, url) in [("auth.device_authorization_url", &auth.device_authorization_url), ("auth.token_url", &auth.token_url)] {
+            if let Err(m) = check_url(url) {
+                issues.push(ConfigIssue::new(field, m));
+            }
+        }
+        if auth.client_id.trim().is_empty() {
+            issues.push(ConfigIssue::new("auth.client_id", "must not be empty"));
+        }
+    }
+    if let Some(pin) = &cfg.enroll_spki_pin {
+        if let Err(m) = crate::tls::parse_pin(pin) {
+            issues.push(ConfigIssue::new("enroll_spki_pin", m));
+        }
+    }
+    if cfg.enroll_token.as_ref().is_some_and(|t| !crate::enroll::is_valid_token(t.expose())) {
+        issues.push(ConfigIssue::new("enroll_token", "must be printable ASCII without spaces"));
+    }
+    if let Some(path) = &cfg.enroll_ca_file {
+        if !std::path::Path::new(path).is_file() {
+            issues.push(ConfigIssue::new("enroll_ca_file", format!("`{path}` is not a readable file")));
+        }
+    }
+    for (i, d) in cfg.tunnel_domains.iter().enumerate() {
+        if !is_valid_domain(d) {
+            issues.push(ConfigIssue::new(format!("tunnel_domains[{i}]"), format!("`{d}` is not a valid domain name")));
+        }
+    }
+    for (i, ep) in cfg.fallback_endpoints.iter().enumerate() {
+        if !is_valid_host_port(ep) {
+            issues.push(ConfigIssue::new(format!("fallback_endpoints[{i}]"), format!("`{ep}` is not a valid host:port")));
+        }
+    }
+    if cfg.handshake_timeout_secs == 0 {
+        issues.push(ConfigIssue::new("handshake_timeout_secs", "must be at least 1"));
+    }
+    if cfg.handshake_poll_ms < 10 || cfg.handshake_poll_ms > cfg.handshake_timeout_secs.saturating_mul(1000) {
+        issues.push(ConfigIssue::new("handshake_poll_ms", "must be between 10 and the handshake timeout"));
+    }
+    let hc = &cfg.health_check;
+    if hc.timeout_secs == 0 {
+        issues.push(ConfigIssue::new("health_check.timeout_secs", "must be at least 1"));
+    }
+    for (i, probe) in hc.probes.iter().enumerate() {
+        if let Err(m) = check_probe(probe) {
+            issues.push(ConfigIssue::new(format!("health_check.probes[{i}]"), m));
+        }
+    }
+    let cp = &cfg.captive_portal;
+    if let Some(url) = &cp.check_url {
+        let res = if url.starts_with("http://") { check_url(url) } else { Err(format!("`{url}` must start with http://")) };
+        if let Err(m) = res {
+            issues.push(ConfigIssue::new("captive_portal.check_url", m));
+        }
+    }
+    if !(100..=599).contains(&cp.expect_status) {
+        issues.push(ConfigIssue::new("captive_portal.expect_status", format!("{} is not an HTTP status", cp.expect_status)));
+    }
+    issues
+}
+
+/**
+ * @brief Find keys in raw TOML that `ClientConfig` does not know about.
+ * @param raw TOML document.
+ * @return One warning per unknown key, suggesting the closest known key when there is one.
+ */
+pub fn unknown_key_warnings(raw: &toml::Value) -> Vec<String> {
+    let mut out = Vec::new();
+    let Some(table) = raw.as_table() else { return out };
+    for (key, value) in table {
+        let sub = value.as_table().filter(|_| KNOWN_KEYS.iter().any(|k| k.starts_with(&format!("{key}."))));
+        match sub {
+            Some(sub) => {
+                for inner in sub.keys() {
+                    check_known(&format!("{key}.{inner}"), &mut out);
+                }
+            }
+            None => check_known(key, &mut out),
+        }
+    }
+    out
+}
+
+fn check_known(path: &str, out: &mut Vec<String>) {
+    if KNOWN_KEYS.contains(&path) { return; }
+    match closest_key(path) {
+        Some(k) => out.push(format!("unknown key `{path}`, did you mean `{k}`?")),
+        None => out.push(format!("unknown key `{path}`")),
+    }
+}
+
+fn closest_key(path: &str) -> Option<&'static str> {
+    KNOWN_KEYS
+        .iter()
+        .map(|k| (levenshtein(path, k), *k))
+        .filter(|(d, _)| *d <= 3.max(path.len() / 3))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn check_key(b64: &str) -> Result<(), String> {
+    match base64::engine::general_purpose::STANDARD.decode(b64) {
+        Ok(bytes) if bytes.len() == 32 => Ok(()),
+        Ok(bytes) => Err(format!("must decode to exactly 32 bytes, got {}", bytes.len())),
+        Err(e) => Err(format!("not valid base64: {e}")),
+    }
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .ok_or_else(|| format!("`{url}` must start with http:// or https://"))?;
+    let host = rest.split('/').next().unwrap_or("");
+    if host.is_empty() || host.starts_with(':') {
+        return Err(format!("`{url}` has no host"));
+    }
+    Ok(())
+}
+
+fn is_valid_domain(d: &str) -> bool {
+    let d = d.trim_end_matches('.');
+    !d.is_empty()
+        && d.len() <= 253
+        && d.split('.').all(|l| {
+            !l.is_empty()
+                && l.len() <= 63
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn is_valid_host_port(s: &str) -> bool {
+    if s.parse::<std::net::SocketAddr>().is_ok() { return true; }
+    match s.rsplit_once(':') {
+        Some((host, port)) => is_valid_domain(host) && port.parse::<u16>().is_ok_and(|p| p != 0),
+        None => false,
+    }
+}
+
+fn check_probe(probe: &Probe) -> Result<(), String> {
+    match probe {
+        Probe::Tcp { target } if !is_valid_host_port(target) => Err(format!("`{target}` is not a valid host:port")),
+        Probe::Icmp { host } if host.parse::<std::net::IpAddr>().is_err() && !is_valid_domain(host) => {
+            Err(format!("`{host}` is not a valid host"))
+        }
+        Probe::Http { url, .. } if !url.starts_with("http://") => Err(format!("`{url}` must start with http://")),
+        Probe::Http { expect_status, .. } if !(100..=599).contains(expect_status) => {
+            Err(format!("expect_status {expect_status} is not an HTTP status"))
+        }
+        Probe::Http { url, .. } => check_url(url),
+        Probe::Dns { name, .. } if !is_valid_domain(name) => Err(format!("`{name}` is not a valid domain name")),
+        Probe::Dns { server: Some(s), .. } if s.parse::<std::net::IpAddr>().is_err() => Err(format!("`{s}` is not an IP address")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,190 @@
+#![allow(dead_code)]
+
+use defguard_wireguard_rs::{host::Peer, InterfaceConfiguration};
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use vpn_client::health::Probe;
+use vpn_client::http::HttpResponse;
+use vpn_client::dns::DnsSnapshot;
+use vpn_client::route::DefaultRoute;
+use vpn_client::config::ClientConfig;
+use vpn_client::system::System;
+use vpn_client::VpnError;
+
+/** All-zero x25519 key (base64), valid as both client and server key in tests. */
+pub const ZERO_KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+/** Stand-in WireGuard public key sent to enrollment servers. */
+pub const PUBKEY: &str = "dGVzdC1wdWJsaWMta2V5LXRlc3QtcHVibGljLWtleS0=";
+
+/**
+ * Config for tunnels driven through a test double: zero keys, split tunnel, no enrollment and
+ * no welcome page. Tests override only the fields they exercise.
+ */
+pub fn test_config() -> ClientConfig {
+    ClientConfig {
+        server_public_key_b64: ZERO_KEY.into(),
+        client_private_key_b64: Some(ZERO_KEY.into()),
+        enroll_url: None,
+        welcome_url: None,
+        split_tunnel: true,
+        ..ClientConfig::default()
+    }
+}
+
+/** System double that records undo calls and pretends an interface exists when asked. */
+#[derive(Default)]
+pub struct RecordingSystem {
+    pub undone: Mutex<Vec<String>>,
+    pub interface_exists: Mutex<bool>,
+    /** Time of the peer's last handshake, reported once the interface exists. */
+    pub handshake: Mutex<Option<SystemTime>>,
+    /** Every peer passed to `configure_peer`, in order. */
+    pub peers: Mutex<Vec<Peer>>,
+    /** Default route reported by `snapshot_default_route`. */
+    pub default_route: Mutex<Option<DefaultRoute>>,
+    /** Host routes and DNS applied, in order. */
+    pub applied: Mutex<Vec<String>>,
+    /** Probes run, in order. */
+    pub probes: Mutex<Vec<String>>,
+    /** Probes that report failure. */
+    pub failing_probes: Mutex<Vec<Probe>>,
+    /** Responses returned by `http_get`, in order; unreachable once exhausted. */
+    pub http_responses: Mutex<VecDeque<HttpResponse>>,
+    /** URLs passed to `open_url`. */
+    pub opened: Mutex<Vec<String>>,
+    /** Resolvers passed to the last `apply_dns`. */
+    pub dns_servers: Mutex<Vec<String>>,
+    /** Private key of every `configure_interface` call, in order. */
+    pub interface_keys: Mutex<Vec<String>>,
+}
+
+impl RecordingSystem {
+    fn log(&self, s: &str) -> Result<(), VpnError> { self.undone.lock().unwrap().push(s.to_string()); Ok(()) }
+    pub fn undone(&self) -> Vec<String> { self.undone.lock().unwrap().clone() }
+    pub fn applied(&self) -> Vec<String> { self.applied.lock().unwrap().clone() }
+}
+
+impl System for RecordingSystem {
+    fn create_interface(&self, _: &str) -> Result<(), VpnError> { *self.interface_exists.lock().unwrap() = true; Ok(()) }
+    fn configure_interface(&self, config: &InterfaceConfiguration) -> Result<(), VpnError> {
+        self.interface_keys.lock().unwrap().push(config.prvkey.clone());
+        Ok(())
+    }
+    fn remove_interface(&self, _: &str) -> Result<(), VpnError> { *self.interface_exists.lock().unwrap() = false; self.log("interface") }
+    fn configure_peer(&self, _: &str, peer: &Peer) -> Result<(), VpnError> { self.peers.lock().unwrap().push(peer.clone()); Ok(()) }
+    fn read_peers(&self, ifname: &str) -> Result<Vec<Peer>, VpnError> {
+        if *self.interface_exists.lock().unwrap() {
+            let last_handshake = *self.handshake.lock().unwrap();
+            if last_handshake.is_none() { return Ok(Vec::new()); }
+            Ok(vec![Peer { last_handshake, ..Peer::default() }])
+        } else { Err(VpnError::SystemCommand(format!("no such device {ifname}"))) }
+    }
+    fn apply_kill_switch(&self, _: &str) -> Result<(), VpnError> { Ok(()) }
+    fn revert_kill_switch(&self, _: &str) -> Result<(), VpnError> { self.log("kill_switch") }
+    fn snapshot_default_route(&self) -> Option<DefaultRoute> { self.default_route.lock().unwrap().clone() }
+    fn route_to(&self, ip: &str) -> Option<String> {
+        self.default_route.lock().unwrap().as_ref().map(|r| format!("{ip} via {} dev {}", r.gateway, r.device))
+    }
+    fn add_host_route(&self, ip: &str, via: &DefaultRoute) -> Result<(), VpnError> {
+        self.applied.lock().unwrap().push(format!("host_route {ip} via {}", via.gateway));
+        Ok(())
+    }
+    fn remove_host_route(&self, _: &str) -> Result<(), VpnError> { self.log("host_route") }
+    fn add_tunnel_route(&self, _: &IpAddr, _: &str) -> Result<(), VpnError> { Ok(()) }
+    fn del_tunnel_route(&self, _: &IpAddr, _: &str) -> Result<(), VpnError> { self.log("tunnel_route") }
+    fn snapshot_dns(&self, ifname: &str) -> DnsSnapshot { DnsSnapshot { ifname: ifname.into(), resolv_conf: None } }
+    fn apply_dns(&self, _: &str, servers: &[String]) -> Result<(), VpnError> {
+        self.applied.lock().unwrap().push("dns".into());
+        *self.dns_servers.lock().unwrap() = servers.to_vec();
+        Ok(())
+    }
+    fn restore_dns(&self, _: &DnsSnapshot) -> Result<(), VpnError> { self.log("dns") }
+    fn probe_connectivity(&self, probe: &Probe, _: Duration) -> Result<(), VpnError> {
+        self.probes.lock().unwrap().push(probe.to_string());
+        match self.failing_probes.lock().unwrap().contains(probe) {
+            true => Err(VpnError::Connectivity(format!("{probe} unreachable"))),
+            false => Ok(()),
+        }
+    }
+    fn http_get(&self, url: &str, _: Duration) -> Result<HttpResponse, VpnError> {
+        self.http_responses.lock().unwrap().pop_front().ok_or_else(|| VpnError::Connectivity(format!("{url} unreachable")))
+    }
+    fn open_url(&self, url: &str) { self.opened.lock().unwrap().push(url.to_string()); }
+}
+
+/** PID of a process that has already exited. */
+pub fn dead_pid() -> u32 {
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    pid
+}
+
+/** Fresh, empty temp dir for one test. */
+pub fn temp_dir(tag: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{tag}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/** Point the runtime directory at a fresh per-test temp dir. */
+pub fn temp_runtime_dir(tag: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{tag}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::env::set_var("VPN_CLIENT_RUNTIME_DIR", &dir);
+    dir
+}
+
+/** Connect to a daemon socket, waiting for `serve` to start listening. */
+#[cfg(unix)]
+pub fn connect_daemon(sock: &std::path::Path) -> vpn_client::daemon::DaemonClient {
+    loop {
+        match vpn_client::daemon::DaemonClient::connect(sock) {
+            Ok(c) => break c,
+            Err(_) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/** HTTP response with `status` (e.g. `"200 OK"`) and `body`. */
+pub fn reply(status: &str, body: &str) -> String {
+    format!("HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n{body}", body.len())
+}
+
+/** Stand-in HTTP server: answers each connection with the next canned response and reports the raw request. */
+pub fn serve_http(responses: Vec<String>) -> (String, std::sync::mpsc::Receiver<String>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for resp in responses {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while let Ok(n) = conn.read(&mut chunk) {
+                if n == 0 { break; }
+                buf.extend_from_slice(&chunk[..n]);
+                if request_complete(&buf) { break; }
+            }
+            tx.send(String::from_utf8_lossy(&buf).into_owned()).unwrap();
+            conn.write_all(resp.as_bytes()).unwrap();
+        }
+    });
+    (base, rx)
+}
+
+fn request_complete(buf: &[u8]) -> bool {
+    let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else { return false };
+    let head = String::from_utf8_lossy(&buf[..end]).to_ascii_lowercase();
+    let len = head.lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    buf.len() >= end + 4 + len
+}
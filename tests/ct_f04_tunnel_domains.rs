@@ -0,0 +1,29 @@
+use std::net::IpAddr;
+use vpn_client::domains::{DomainTable, MIN_TTL_SECS};
+
+#[test]
+fn ct_f04_tunnel_domains_follow_dns_answers_and_expire() {
+    let domains = vec!["intranet.example".to_string()];
+    let a: IpAddr = "10.1.0.5".parse().unwrap();
+    let b: IpAddr = "10.1.0.6".parse().unwrap();
+    let mut table = DomainTable::default();
+
+    let delta = table.refresh(&domains, 1000, |_| vec![(a, 300)]);
+    assert_eq!(delta.added, vec![a]);
+    assert!(delta.removed.is_empty());
+
+    // Still within TTL: no lookup, no change.
+    let delta = table.refresh(&domains, 1100, |_| panic!("resolved before expiry"));
+    assert!(delta.is_empty());
+
+    // TTL elapsed and the name now points elsewhere.
+    let delta = table.refresh(&domains, 1300, |_| vec![(b, 5)]);
+    assert_eq!(delta.added, vec![b]);
+    assert_eq!(delta.removed, vec![a]);
+    assert_eq!(table.entries[0].expires_at, 1300 + MIN_TTL_SECS);
+
+    // Resolution fails after expiry: the address is withdrawn.
+    let delta = table.refresh(&domains, 1300 + MIN_TTL_SECS, |_| Vec::new());
+    assert_eq!(delta.removed, vec![b]);
+    assert!(table.addresses().is_empty());
+}
@@ -0,0 +1,32 @@
+use vpn_client::build_interface_config;
+use vpn_client::config::ClientConfig;
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f05_config_errors_are_typed_with_distinct_exit_codes() {
+    let mut cfg = ClientConfig { client_private_key_b64: Some("AAAA".into()), ..ClientConfig::default() };
+    cfg.server_public_key_b64 = "c2hvcnQ=".into();
+    let err = build_interface_config(&cfg, "wg-test").unwrap_err();
+    assert!(matches!(err, VpnError::InvalidKey(_)));
+
+    cfg.server_public_key_b64 = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into();
+    cfg.server_endpoint = "not-an-endpoint".into();
+    let err = build_interface_config(&cfg, "wg-test").unwrap_err();
+    assert!(matches!(err, VpnError::InvalidEndpoint(_)));
+
+    let codes = [
+        VpnError::ConfigParse(String::new()).exit_code(),
+        VpnError::InvalidKey(String::new()).exit_code(),
+        VpnError::InvalidCidr(String::new()).exit_code(),
+        VpnError::InvalidEndpoint(String::new()).exit_code(),
+        VpnError::Enrollment(String::new()).exit_code(),
+        VpnError::HandshakeTimeout(String::new()).exit_code(),
+        VpnError::Connectivity(String::new()).exit_code(),
+        VpnError::SystemCommand(String::new()).exit_code(),
+    ];
+    let mut unique = codes.to_vec();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), codes.len());
+    assert!(codes.iter().all(|&c| c != 0 && c != 1 && c != 2));
+}
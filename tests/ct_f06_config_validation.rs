@@ -0,0 +1,26 @@
+use vpn_client::config::{check_config_file, load_client_config};
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f06_config_check_collects_all_problems_with_field_paths() {
+    let p = std::env::temp_dir().join(format!("ct_f06_{}.toml", std::process::id()));
+    std::fs::write(&p, r#"
+interface_name = "wg-client"
+address_cidr = "10.8.0.300/32"
+server_endpoint = "vpn.example"
+server_public_key_b64 = "not base64!"
+keepalive_secs = 25
+split_tunnel = true
+kill_swich = true
+kill_switch = false
+enroll_url = "ftp://example"
+tunnel_domains = ["ok.example", "bad_domain..x"]
+"#).unwrap();
+    let report = check_config_file(&p).unwrap();
+    let fields: Vec<&str> = report.issues.iter().map(|i| i.field.as_str()).collect();
+    assert_eq!(fields, ["address_cidr", "server_endpoint", "server_public_key_b64", "enroll_url", "tunnel_domains[1]"]);
+    assert_eq!(report.warnings, ["unknown key `kill_swich`, did you mean `kill_switch`?"]);
+    let err = load_client_config(Some(p.clone())).unwrap_err();
+    assert!(matches!(err, VpnError::InvalidConfig(ref issues) if issues.len() == 5));
+    let _ = std::fs::remove_file(&p);
+}
@@ -0,0 +1,12 @@
+use vpn_client::route::{parse_default_route, DefaultRoute};
+
+#[test]
+fn ct_f07_default_route_is_parsed_from_ip_route_output() {
+    let out = "default via 192.168.1.1 dev wlan0 proto dhcp src 192.168.1.20 metric 600\n";
+    assert_eq!(
+        parse_default_route(out),
+        Some(DefaultRoute { gateway: "192.168.1.1".into(), device: "wlan0".into() })
+    );
+    assert_eq!(parse_default_route("default dev wg0 scope link\n"), None);
+    assert_eq!(parse_default_route(""), None);
+}
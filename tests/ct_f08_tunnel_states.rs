@@ -0,0 +1,21 @@
+use vpn_client::config::ClientConfig;
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f08_failed_setup_reports_state_transitions() {
+    let cfg = ClientConfig {
+        client_private_key_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into()),
+        server_public_key_b64: "too-short".into(),
+        enroll_url: None,
+        ..ClientConfig::default()
+    };
+    let mut tunnel = Tunnel::new(cfg, Some("wg-ct-f08".into()));
+    let events = tunnel.subscribe();
+    assert_eq!(tunnel.state(), TunnelState::Idle);
+    let err = tunnel.up().unwrap_err();
+    assert!(matches!(err, VpnError::InvalidKey(_)));
+    assert_eq!(tunnel.state(), TunnelState::Failed);
+    let seen: Vec<TunnelState> = events.try_iter().map(|e| e.to).collect();
+    assert_eq!(seen, [TunnelState::Enrolling, TunnelState::Configuring, TunnelState::TearingDown, TunnelState::Failed]);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{dead_pid, temp_runtime_dir, RecordingSystem};
+use std::sync::Arc;
+use std::time::Duration;
+use vpn_client::dns::DnsSnapshot;
+use vpn_client::journal::{Change, Journal};
+use vpn_client::state::{self, RuntimeState};
+
+#[test]
+fn ct_f09_disconnect_replays_undo_steps_from_state_file() {
+    let dir = temp_runtime_dir("ct_f09");
+    let ifname = "wg-ct-f09";
+    let path = state::state_path(ifname);
+
+    // A journal mirrors its changes to the state file as they are recorded.
+    let sys = Arc::new(RecordingSystem::default());
+    let mut journal = Journal::new(sys.clone());
+    journal.persist_to(ifname, path.clone());
+    journal.record(Change::Interface { ifname: ifname.into() });
+    journal.record(Change::KillSwitch { ifname: ifname.into() });
+    journal.record(Change::HostRoute { endpoint_ip: "203.0.113.7".into() });
+    journal.record(Change::Dns { snapshot: DnsSnapshot { ifname: ifname.into(), resolv_conf: Some("nameserver 192.168.1.1\n".into()) } });
+    let saved = RuntimeState::load(&path).unwrap();
+    assert_eq!(saved.changes, journal.changes());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    // Simulate a crashed owner: the journal never gets to unwind.
+    std::mem::forget(journal);
+
+    // Pretend the owner has already exited, then disconnect from "another process".
+    RuntimeState { pid: dead_pid(), ..saved }.save(&path).unwrap();
+    let other = RecordingSystem::default();
+    assert!(state::disconnect(&other, ifname, Duration::from_secs(1)).unwrap());
+    assert_eq!(other.undone(), ["dns", "host_route", "kill_switch", "interface"]);
+    assert!(!path.exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
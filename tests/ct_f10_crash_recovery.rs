@@ -0,0 +1,51 @@
+mod common;
+
+use common::{dead_pid, temp_runtime_dir, RecordingSystem};
+use vpn_client::dns::DnsSnapshot;
+use vpn_client::journal::Change;
+use vpn_client::state::{self, RuntimeState};
+
+#[test]
+fn ct_f10_leftovers_are_detected_and_recovered() {
+    let dir = temp_runtime_dir("ct_f10");
+    let ifname = "wg-ct-f10";
+    let sys = RecordingSystem::default();
+    assert!(state::detect_leftovers(&sys, ifname).is_clean());
+
+    // Interface left behind with no state file: stale, but needs --force.
+    *sys.interface_exists.lock().unwrap() = true;
+    let left = state::detect_leftovers(&sys, ifname);
+    assert!(left.is_stale());
+    assert!(!left.is_recoverable_automatically());
+
+    // State file from a crashed owner: recovered automatically, newest change first.
+    RuntimeState {
+        ifname: ifname.into(),
+        pid: dead_pid(),
+        changes: vec![
+            Change::Interface { ifname: ifname.into() },
+            Change::Dns { snapshot: DnsSnapshot { ifname: ifname.into(), resolv_conf: Some("nameserver 192.168.1.1\n".into()) } },
+            Change::KillSwitch { ifname: ifname.into() },
+        ],
+    }
+    .save(&state::state_path(ifname))
+    .unwrap();
+    let left = state::detect_leftovers(&sys, ifname);
+    assert!(left.is_recoverable_automatically());
+    let actions = state::recover(&sys, ifname, false).unwrap();
+    assert_eq!(sys.undone(), ["kill_switch", "dns", "interface"]);
+    assert_eq!(actions, ["reverted kill switch for wg-ct-f10", "restored DNS for wg-ct-f10", "removed interface wg-ct-f10"]);
+    assert!(state::detect_leftovers(&sys, ifname).is_clean());
+
+    // Owner still alive: recovery refuses to touch it.
+    let mut owner = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+    RuntimeState { ifname: ifname.into(), pid: owner.id(), changes: vec![Change::KillSwitch { ifname: ifname.into() }] }
+        .save(&state::state_path(ifname))
+        .unwrap();
+    let left = state::detect_leftovers(&sys, ifname);
+    assert!(left.owner_alive && !left.is_stale());
+    assert!(state::recover(&sys, ifname, false).is_err());
+    owner.kill().unwrap();
+    owner.wait().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
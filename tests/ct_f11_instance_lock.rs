@@ -0,0 +1,47 @@
+mod common;
+
+use common::{dead_pid, temp_runtime_dir};
+use vpn_client::lock::{lock_owner, InstanceLock};
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f11_second_owner_is_refused_with_pid() {
+    let dir = temp_runtime_dir("ct_f11");
+    let ifname = "wg-ct-f11";
+    assert_eq!(lock_owner(ifname), None);
+
+    let held = InstanceLock::acquire(ifname).unwrap();
+    assert_eq!(lock_owner(ifname), Some(std::process::id()));
+    match InstanceLock::acquire(ifname) {
+        Err(e @ VpnError::AlreadyRunning { .. }) => {
+            assert!(e.to_string().contains(&std::process::id().to_string()));
+            assert_eq!(e.exit_code(), 20);
+        }
+        other => panic!("expected AlreadyRunning, got {other:?}"),
+    }
+
+    drop(held);
+    assert_eq!(lock_owner(ifname), None);
+    assert!(InstanceLock::acquire(ifname).is_ok());
+    #[cfg(unix)]
+    owner_is_read_without_locking("wg-ct-f11p");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+fn owner_is_read_without_locking(ifname: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    let path = vpn_client::lock::lock_path(ifname);
+
+    // A PID left behind by a crashed owner does not count.
+    std::fs::write(&path, format!("{}\n", dead_pid())).unwrap();
+    assert_eq!(lock_owner(ifname), None);
+
+    let held = InstanceLock::acquire(ifname).unwrap();
+    // Probing needs only read access (e.g. `status` on a root-owned lock) and does not disturb the holder.
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+    assert_eq!(lock_owner(ifname), Some(std::process::id()));
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    drop(held);
+    assert_eq!(lock_owner(ifname), None);
+}
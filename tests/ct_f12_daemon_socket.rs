@@ -0,0 +1,70 @@
+mod common;
+
+use common::{connect_daemon, temp_runtime_dir, test_config, RecordingSystem};
+use std::sync::{atomic::Ordering, Arc};
+use std::thread;
+use vpn_client::config::ClientConfig;
+use vpn_client::daemon::{Daemon, DaemonClient, Reply, Request};
+use vpn_client::tunnel::TunnelState;
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f12_daemon_serves_up_status_down_and_events() {
+    let dir = temp_runtime_dir("ct_f12");
+    let sock = dir.join("vpn-client.sock");
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(std::time::SystemTime::now());
+    let cfg = ClientConfig { interface_name: "wg-ct-f12".into(), ..test_config() };
+    let daemon = Daemon::with_system(cfg, None, sys.clone());
+    let stop = daemon.stop_handle();
+    let server = {
+        let (daemon, sock) = (daemon.clone(), sock.clone());
+        thread::spawn(move || daemon.serve(&sock, None))
+    };
+    let mut client = connect_daemon(&sock);
+
+    let mut events = DaemonClient::connect(&sock).unwrap();
+    events.request(&Request::SubscribeEvents).unwrap();
+
+    assert!(matches!(client.request(&Request::Up { ifname: None, force: false }).unwrap(), Reply::Ok { .. }));
+    match client.request(&Request::Status { ifname: None }).unwrap() {
+        Reply::Status { status, owner_pid } => {
+            assert_eq!(status.state, TunnelState::Connected);
+            assert_eq!(owner_pid, Some(std::process::id()));
+        }
+        other => panic!("unexpected reply {other:?}"),
+    }
+    let err = client.request(&Request::Up { ifname: None, force: false }).unwrap_err();
+    assert!(matches!(err, VpnError::Daemon { code: 20, .. }));
+
+    let mut seen = Vec::new();
+    while seen.last() != Some(&TunnelState::Connected) {
+        match events.next_reply().unwrap() {
+            Some(Reply::Event { ifname, event }) => {
+                assert_eq!(ifname, "wg-ct-f12");
+                seen.push(event.to);
+            }
+            other => panic!("unexpected reply {other:?}"),
+        }
+    }
+    assert_eq!(seen.first(), Some(&TunnelState::Enrolling));
+
+    // Client-supplied names end up in runtime file paths and `ip` arguments.
+    for bad in ["../x", "..", "a/b", "wg-name-far-too-long"] {
+        let name = Some(bad.to_string());
+        for req in [Request::Up { ifname: name.clone(), force: true }, Request::Status { ifname: name.clone() }, Request::Down { ifname: name }] {
+            let err = client.request(&req).unwrap_err();
+            assert!(matches!(err, VpnError::Daemon { code: 19, ref message } if message.contains("ifname")), "{bad}: {err}");
+        }
+    }
+    assert!(!dir.join("../x.lock").exists());
+    assert_eq!(sys.undone(), Vec::<String>::new(), "nothing was touched");
+
+    assert!(matches!(client.request(&Request::Down { ifname: None }).unwrap(), Reply::Ok { .. }));
+    assert_eq!(sys.undone(), ["interface"]);
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap().unwrap();
+    assert!(!sock.exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
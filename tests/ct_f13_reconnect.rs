@@ -0,0 +1,46 @@
+mod common;
+
+use common::{test_config, RecordingSystem};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use vpn_client::config::ClientConfig;
+use vpn_client::tunnel::{backoff_delay, Tunnel, TunnelState, MAX_BACKOFF_SECS};
+
+#[test]
+fn ct_f13_stale_handshake_reconnects_to_next_endpoint() {
+    assert_eq!(backoff_delay(0), Duration::from_secs(5));
+    assert_eq!(backoff_delay(2), Duration::from_secs(20));
+    assert_eq!(backoff_delay(40), Duration::from_secs(MAX_BACKOFF_SECS));
+
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let cfg = ClientConfig {
+        server_endpoint: "127.0.0.1:51820".into(),
+        fallback_endpoints: vec!["127.0.0.2:51820".into()],
+        ..test_config()
+    };
+    let mut tunnel = Tunnel::with_system(cfg, Some("wg-ct-f13".into()), sys.clone());
+    let events = tunnel.subscribe();
+    tunnel.up().unwrap();
+    tunnel.poll();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+    assert!(sys.peers.lock().unwrap().is_empty());
+
+    // Four minutes without a handshake: the first attempt re-applies the primary endpoint.
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now() - Duration::from_secs(240));
+    tunnel.poll();
+    assert_eq!(tunnel.state(), TunnelState::Reconnecting);
+    let endpoints: Vec<_> = sys.peers.lock().unwrap().iter().map(|p| p.endpoint.unwrap().to_string()).collect();
+    assert_eq!(endpoints, ["127.0.0.1:51820"]);
+
+    // Still inside the backoff window: no new attempt.
+    tunnel.poll();
+    assert_eq!(sys.peers.lock().unwrap().len(), 1);
+
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    tunnel.poll();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+    let seen: Vec<TunnelState> = events.try_iter().map(|e| e.to).skip_while(|s| *s != TunnelState::Connected).collect();
+    assert_eq!(seen, [TunnelState::Connected, TunnelState::Reconnecting, TunnelState::Connected]);
+    tunnel.down().unwrap();
+}
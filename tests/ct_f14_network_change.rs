@@ -0,0 +1,44 @@
+mod common;
+
+use common::{test_config, RecordingSystem};
+use std::sync::Arc;
+use std::time::SystemTime;
+use vpn_client::config::ClientConfig;
+use vpn_client::netwatch::{encode_message, parse_events, NetEvent};
+use vpn_client::route::DefaultRoute;
+use vpn_client::tunnel::Tunnel;
+
+fn route(gateway: &str, device: &str) -> Option<DefaultRoute> {
+    Some(DefaultRoute { gateway: gateway.into(), device: device.into() })
+}
+
+#[test]
+fn ct_f14_default_route_change_moves_endpoint_route() {
+    // RTM_NEWROUTE for 0.0.0.0/0, RTM_NEWROUTE for a /24, RTM_NEWLINK, and an ignored NLMSG_DONE.
+    let mut buf = encode_message(24, &[2, 0, 0, 0, 254, 3, 0, 1]);
+    buf.extend(encode_message(24, &[2, 24, 0, 0, 254, 3, 0, 1]));
+    buf.extend(encode_message(16, &[0; 16]));
+    buf.extend(encode_message(3, &[0; 4]));
+    let events = parse_events(&buf);
+    assert_eq!(events, [NetEvent::Route { default: true }, NetEvent::Route { default: false }, NetEvent::Link]);
+
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    *sys.default_route.lock().unwrap() = route("192.168.1.1", "wlan0");
+    let cfg = ClientConfig { split_tunnel: false, ..test_config() };
+    let mut tunnel = Tunnel::with_system(cfg, Some("wg-ct-f14".into()), sys.clone());
+    tunnel.up().unwrap();
+    assert_eq!(sys.applied(), ["host_route 127.0.0.1 via 192.168.1.1", "dns"]);
+
+    // Same gateway: nothing to do.
+    assert!(!tunnel.handle_network_events(&events));
+
+    // Wi-Fi to Ethernet.
+    *sys.default_route.lock().unwrap() = route("10.0.0.1", "eth0");
+    assert!(tunnel.handle_network_events(&[NetEvent::Route { default: true }]));
+    assert_eq!(sys.undone(), ["host_route"]);
+    assert_eq!(sys.applied(), ["host_route 127.0.0.1 via 192.168.1.1", "dns", "host_route 127.0.0.1 via 10.0.0.1", "dns"]);
+    assert_eq!(sys.peers.lock().unwrap().len(), 1);
+    assert!(!tunnel.handle_network_events(&[NetEvent::Address]));
+    tunnel.down().unwrap();
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::RecordingSystem;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use vpn_client::config::ClientConfig;
+use vpn_client::health::{run_probe, HealthPolicy, Probe};
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::validate::{unknown_key_warnings, validate_config};
+use vpn_client::VpnError;
+
+const CONFIG: &str = r#"
+interface_name = "wg-ct-f15"
+address_cidr = "10.8.0.2/32"
+server_endpoint = "127.0.0.1:51820"
+server_public_key_b64 = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+client_private_key_b64 = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+keepalive_secs = 25
+split_tunnel = true
+kill_switch = false
+
+[health_check]
+policy = "any"
+timeout_secs = 2
+interval_secs = 1
+
+[[health_check.probes]]
+kind = "tcp"
+target = "10.8.0.1:22"
+
+[[health_check.probes]]
+kind = "dns"
+name = "intranet.example.com"
+server = "10.8.0.1"
+"#;
+
+#[test]
+fn ct_f15_health_check_policy_and_periodic_failure() {
+    let raw: toml::Value = toml::from_str(CONFIG).unwrap();
+    assert!(unknown_key_warnings(&raw).is_empty());
+    let cfg: ClientConfig = toml::from_str(CONFIG).unwrap();
+    assert!(validate_config(&cfg).is_empty());
+    assert_eq!(cfg.health_check.policy, HealthPolicy::Any);
+    let tcp = cfg.health_check.probes[0].clone();
+    let dns = cfg.health_check.probes[1].clone();
+
+    let mut bad = cfg.clone();
+    bad.health_check.probes.push(Probe::Http { url: "ftp://x".into(), expect_status: 200 });
+    assert_eq!(validate_config(&bad)[0].field, "health_check.probes[2]");
+
+    // Policy "any": one failing probe is tolerated.
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    sys.failing_probes.lock().unwrap().push(tcp.clone());
+    let mut tunnel = Tunnel::with_system(cfg.clone(), None, sys.clone());
+    tunnel.up().unwrap();
+
+    // Both failing on a later periodic check: the tunnel reports it and starts reconnecting.
+    sys.failing_probes.lock().unwrap().push(dns);
+    std::thread::sleep(Duration::from_millis(1100));
+    tunnel.poll();
+    assert_eq!(tunnel.state(), TunnelState::Reconnecting);
+    tunnel.down().unwrap();
+
+    // Policy "all" on setup: a failing probe aborts and rolls back.
+    let mut strict = cfg;
+    strict.health_check.policy = HealthPolicy::All;
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    sys.failing_probes.lock().unwrap().push(tcp);
+    let err = Tunnel::with_system(strict, None, sys.clone()).up().unwrap_err();
+    assert!(matches!(err, VpnError::Connectivity(_)));
+    assert_eq!(sys.undone(), ["interface"]);
+}
+
+#[test]
+fn ct_f15_dns_probe_defaults_to_the_tunnel_resolver() {
+    let probe = Probe::Dns { name: "intranet.example.com".into(), server: None };
+    let mut cfg: ClientConfig = toml::from_str(CONFIG).unwrap();
+    cfg.health_check.probes = vec![probe];
+    cfg.dns_servers = vec!["10.8.0.53".into(), "10.8.0.54".into()];
+    for (split_tunnel, expected) in [(false, "dns intranet.example.com @10.8.0.53"), (true, "dns intranet.example.com")] {
+        let sys = Arc::new(RecordingSystem::default());
+        *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+        let mut tunnel = Tunnel::with_system(ClientConfig { split_tunnel, ..cfg.clone() }, None, sys.clone());
+        tunnel.up().unwrap();
+        assert_eq!(*sys.probes.lock().unwrap(), [expected]);
+        tunnel.down().unwrap();
+    }
+}
+
+#[test]
+fn ct_f15_http_probe_checks_status() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/health", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 512];
+            let _ = s.read(&mut buf);
+            s.write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n").unwrap();
+        }
+    });
+    let timeout = Duration::from_secs(2);
+    assert!(run_probe(&Probe::Http { url: url.clone(), expect_status: 204 }, timeout).is_ok());
+    assert!(run_probe(&Probe::Http { url, expect_status: 200 }, timeout).is_err());
+    server.join().unwrap();
+}
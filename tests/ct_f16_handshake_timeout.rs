@@ -0,0 +1,41 @@
+mod common;
+
+use common::{test_config, RecordingSystem};
+use std::sync::Arc;
+use vpn_client::config::ClientConfig;
+use vpn_client::route::DefaultRoute;
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::validate::validate_config;
+use vpn_client::VpnError;
+
+#[test]
+fn ct_f16_handshake_retries_then_times_out_with_diagnostics() {
+    let cfg = ClientConfig {
+        kill_switch: true,
+        handshake_timeout_secs: 1,
+        handshake_poll_ms: 50,
+        handshake_retries: 1,
+        retry_backoff_secs: 0,
+        ..test_config()
+    };
+    assert!(validate_config(&cfg).is_empty());
+    let too_fast = ClientConfig { handshake_poll_ms: 5, ..cfg.clone() };
+    assert_eq!(validate_config(&too_fast)[0].field, "handshake_poll_ms");
+
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.default_route.lock().unwrap() = Some(DefaultRoute { gateway: "10.0.0.1".into(), device: "eth0".into() });
+    let mut tunnel = Tunnel::with_system(cfg, Some("wg-ct-f16".into()), sys.clone());
+    let events = tunnel.subscribe();
+    let err = tunnel.up().unwrap_err();
+    let VpnError::HandshakeTimeout(msg) = &err else { panic!("expected a handshake timeout, got {err:?}") };
+    assert!(msg.contains("within 1s after 2 attempt(s)"), "{msg}");
+    assert!(msg.contains("tx 0 B, rx 0 B, nothing sent"), "{msg}");
+    assert!(msg.contains("endpoint route: 127.0.0.1 via 10.0.0.1 dev eth0"), "{msg}");
+    assert!(msg.contains("kill switch: on"), "{msg}");
+
+    // The retry re-applied the peer and was reported as a new Handshaking step.
+    assert_eq!(sys.peers.lock().unwrap().len(), 1);
+    let handshaking = events.try_iter().filter(|e| e.to == TunnelState::Handshaking).count();
+    assert_eq!(handshaking, 2);
+    assert_eq!(sys.undone(), ["kill_switch", "interface"]);
+}
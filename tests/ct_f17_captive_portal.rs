@@ -0,0 +1,61 @@
+mod common;
+
+use common::{test_config, RecordingSystem};
+use std::sync::Arc;
+use std::time::SystemTime;
+use vpn_client::captive::{classify, CaptivePortal, PortalCheck};
+use vpn_client::config::ClientConfig;
+use vpn_client::http::{parse_response, HttpResponse};
+use vpn_client::tunnel::{Tunnel, TunnelState};
+
+const CHECK: &str = "http://connectivitycheck.gstatic.com/generate_204";
+
+fn response(raw: &str) -> HttpResponse {
+    parse_response(raw.as_bytes()).unwrap()
+}
+
+#[test]
+fn ct_f17_portal_is_detected_opened_and_waited_for() {
+    let cp = CaptivePortal { check_url: Some(CHECK.into()), ..CaptivePortal::default() };
+    let redirect = response("HTTP/1.1 302 Found\r\nLocation: http://portal.hotel/login\r\nContent-Length: 0\r\n\r\n");
+    let open = response("HTTP/1.1 204 No Content\r\n\r\n");
+    let splash = response("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n<html>\r\n7\r\nWelcome\r\n0\r\n\r\n");
+    assert_eq!(splash.body, b"<html>Welcome");
+    assert_eq!(classify(&cp, &open), PortalCheck::Open);
+    assert_eq!(classify(&cp, &redirect), PortalCheck::Portal { login_url: "http://portal.hotel/login".into() });
+    assert_eq!(classify(&cp, &splash), PortalCheck::Portal { login_url: CHECK.into() });
+    let body_cp = CaptivePortal { expect_status: 200, expect_body: Some("success".into()), ..cp.clone() };
+    assert_eq!(classify(&body_cp, &response("HTTP/1.1 200 OK\r\n\r\nsuccess\n")), PortalCheck::Open);
+
+    let cfg = ClientConfig { captive_portal: cp, ..test_config() };
+
+    // Redirected first, open after the user logs in.
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    sys.http_responses.lock().unwrap().extend([redirect, open.clone()]);
+    let mut tunnel = Tunnel::with_system(cfg.clone(), Some("wg-ct-f17".into()), sys.clone());
+    let events = tunnel.subscribe();
+    tunnel.up().unwrap();
+    assert_eq!(*sys.opened.lock().unwrap(), ["http://portal.hotel/login"]);
+    let states: Vec<TunnelState> = events.try_iter().map(|e| e.to).collect();
+    assert_eq!(states[..4], [TunnelState::Preflight, TunnelState::Preflight, TunnelState::Preflight, TunnelState::Enrolling]);
+    tunnel.down().unwrap();
+
+    // A redirect to anything but a plain http(s) URL is not opened; the check URL leads to the portal too.
+    for location in ["file:///etc/passwd", "javascript:alert(1)", "http://portal.hotel/x' ; calc ; '"] {
+        let redirect = response(&format!("HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\n\r\n"));
+        let sys = Arc::new(RecordingSystem::default());
+        *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+        sys.http_responses.lock().unwrap().extend([redirect, open.clone()]);
+        let mut tunnel = Tunnel::with_system(cfg.clone(), Some("wg-ct-f17".into()), sys.clone());
+        tunnel.up().unwrap();
+        assert_eq!(*sys.opened.lock().unwrap(), [CHECK], "{location}");
+        tunnel.down().unwrap();
+    }
+
+    // Check URL unreachable: not treated as a portal.
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    Tunnel::with_system(cfg, Some("wg-ct-f17".into()), sys.clone()).up().unwrap();
+    assert!(sys.opened.lock().unwrap().is_empty());
+}
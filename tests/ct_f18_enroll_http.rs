@@ -0,0 +1,101 @@
+mod common;
+
+use common::{serve_http, PUBKEY};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+use vpn_client::enroll::{enroll, EnrollOptions};
+use vpn_client::http::parse_url;
+use vpn_client::VpnError;
+
+fn fast() -> EnrollOptions {
+    EnrollOptions { connect_timeout: Duration::from_secs(2), read_timeout: Duration::from_millis(500), max_redirects: 3, ..EnrollOptions::default() }
+}
+
+#[test]
+fn ct_f18_enroll_posts_key_and_handles_status_codes() {
+    // Success with a chunked body.
+    let (base, rx) = serve_http(vec![
+        "HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nwel\r\n4\r\ncome\r\n0\r\n\r\n".into(),
+    ]);
+    let resp = enroll(&format!("{base}/api/enroll?site=lab"), PUBKEY, &fast()).unwrap();
+    assert_eq!(resp.status, 201);
+    assert_eq!(resp.body, b"welcome");
+    let req = rx.recv().unwrap();
+    assert!(req.starts_with("POST /api/enroll?site=lab HTTP/1.1\r\n"), "{req}");
+    assert!(req.contains(&format!("Host: {}\r\n", base.trim_start_matches("http://"))));
+    assert!(req.contains(&format!("Content-Length: {}\r\n", PUBKEY.len())));
+    assert!(req.ends_with(&format!("\r\n\r\n{PUBKEY}")));
+
+    // Rejections carry the status code and the server's explanation.
+    let (base, _rx) = serve_http(vec![
+        "HTTP/1.1 400 Bad Request\r\nContent-Length: 13\r\n\r\nmalformed key".into(),
+        "HTTP/1.0 503 Service Unavailable\r\n\r\n".into(),
+    ]);
+    match enroll(&format!("{base}/enroll"), PUBKEY, &fast()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("rejected (400): malformed key"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+    match enroll(&format!("{base}/enroll"), PUBKEY, &fast()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("server error (503)"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+
+    // A connection closed before the announced body arrived is not a valid answer.
+    let (base, _rx) = serve_http(vec!["HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\n{\"ok\":".into()]);
+    match enroll(&format!("{base}/enroll"), PUBKEY, &fast()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("truncated"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+}
+
+#[test]
+fn ct_f18_enroll_follows_redirects_with_a_limit() {
+    let (base, rx) = serve_http(vec![
+        "HTTP/1.1 308 Permanent Redirect\r\nLocation: /v2/enroll\r\nContent-Length: 0\r\n\r\n".into(),
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".into(),
+    ]);
+    let resp = enroll(&format!("{base}/enroll"), PUBKEY, &fast()).unwrap();
+    assert_eq!(resp.body, b"ok");
+    assert!(rx.recv().unwrap().starts_with("POST /enroll "));
+    let second = rx.recv().unwrap();
+    assert!(second.starts_with("POST /v2/enroll "), "{second}");
+    assert!(second.ends_with(PUBKEY), "the key is re-sent after a 308");
+
+    let looping = "HTTP/1.1 302 Found\r\nLocation: /enroll\r\nContent-Length: 0\r\n\r\n".to_string();
+    let (base, _rx) = serve_http(vec![looping; 4]);
+    match enroll(&format!("{base}/enroll"), PUBKEY, &fast()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("more than 3 redirects"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+}
+
+#[test]
+fn ct_f18_enroll_times_out_and_parses_urls() {
+    // Server accepts but never answers: the read timeout ends the attempt.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/enroll", listener.local_addr().unwrap());
+    let hold = thread::spawn(move || {
+        let (conn, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(2));
+        drop(conn);
+    });
+    let started = Instant::now();
+    match enroll(&url, PUBKEY, &fast()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("timed out"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    hold.join().unwrap();
+
+    let u = parse_url("http://[::1]:8080/enroll?x=1#frag").unwrap();
+    assert_eq!((u.host.as_str(), u.port, u.path.as_str(), u.https), ("::1", 8080, "/enroll?x=1", false));
+    assert_eq!(u.to_string(), "http://[::1]:8080/enroll?x=1");
+    let u = parse_url("https://vpn.example.com").unwrap();
+    assert_eq!((u.port, u.path.as_str(), u.https), (443, "/", true));
+    assert_eq!(u.join("http://other:81/x").unwrap().to_string(), "http://other:81/x");
+    assert!(parse_url("ftp://vpn.example.com/").is_err());
+    assert!(parse_url("http://user@vpn.example.com/").is_err());
+    assert!(parse_url("http://vpn.example.com:http/").is_err());
+    assert!(matches!(enroll("ftp://x/", PUBKEY, &fast()), Err(VpnError::Enrollment(_))));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use common::{PUBKEY, ZERO_KEY};
+use vpn_client::config::ClientConfig;
+use vpn_client::enroll::{enroll, EnrollOptions};
+use vpn_client::tls::{spki_pin_of, TlsSettings};
+use vpn_client::validate::validate_config;
+use vpn_client::VpnError;
+
+/** Local CA, a server certificate for 127.0.0.1 signed by it, and the CA written out as a PEM bundle. */
+struct Fixture {
+    ca_file: PathBuf,
+    chain: Vec<CertificateDer<'static>>,
+    key_der: Vec<u8>,
+    server_pin: String,
+}
+
+fn fixture(tag: &str) -> Fixture {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server = CertificateParams::new(vec!["127.0.0.1".to_string()]).unwrap()
+        .signed_by(&server_key, &ca, &ca_key).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("vpn-client-{tag}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ca_file = dir.join("ca.pem");
+    std::fs::write(&ca_file, ca.pem()).unwrap();
+    let server_pin = format!("sha256/{}", STANDARD.encode(Sha256::digest(server_key.public_key_der())));
+    Fixture { ca_file, chain: vec![server.der().clone()], key_der: server_key.serialize_der(), server_pin }
+}
+
+/** HTTPS stand-in: serves `conns` connections, answering each enrollment with 200. */
+fn serve_tls(fx: &Fixture, conns: usize) -> String {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(fx.key_der.clone()));
+    let cfg = Arc::new(rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions().unwrap()
+        .with_no_client_auth()
+        .with_single_cert(fx.chain.clone(), key).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://{}/enroll", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for _ in 0..conns {
+            let (tcp, _) = listener.accept().unwrap();
+            tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut tls = rustls::StreamOwned::new(rustls::ServerConnection::new(cfg.clone()).unwrap(), tcp);
+            let mut buf = [0u8; 2048];
+            // a client that rejects the certificate aborts the handshake; just move on
+            if tls.read(&mut buf).is_err() { continue; }
+            let _ = tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nenrolled");
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        }
+    });
+    url
+}
+
+fn options(tls: TlsSettings) -> EnrollOptions {
+    EnrollOptions { connect_timeout: Duration::from_secs(2), read_timeout: Duration::from_secs(2), tls, ..EnrollOptions::default() }
+}
+
+fn expect_tls_failure(res: Result<vpn_client::http::HttpResponse, VpnError>, needle: &str) {
+    match res {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("TLS") && m.contains(needle), "{m}"),
+        other => panic!("expected a TLS enrollment failure, got {other:?}"),
+    }
+}
+
+#[test]
+fn ct_f19_https_enrollment_verifies_ca_and_pin() {
+    let fx = fixture("ct-f19");
+    assert_eq!(spki_pin_of(&fx.chain[0]).unwrap(), fx.server_pin);
+    let url = serve_tls(&fx, 4);
+
+    // Trusted through the configured CA bundle.
+    let trusted = TlsSettings::from_config(fx.ca_file.to_str(), None).unwrap();
+    let resp = enroll(&url, PUBKEY, &options(trusted)).unwrap();
+    assert_eq!(resp.body, b"enrolled");
+
+    // Without the bundle the private CA is unknown: a hard error, not a silent skip.
+    expect_tls_failure(enroll(&url, PUBKEY, &options(TlsSettings::default())), "UnknownIssuer");
+
+    // CA plus matching pin succeeds; a different pin is rejected even though the chain is valid.
+    let pinned = TlsSettings::from_config(fx.ca_file.to_str(), Some(&fx.server_pin)).unwrap();
+    assert_eq!(enroll(&url, PUBKEY, &options(pinned)).unwrap().status, 200);
+    let wrong = format!("sha256/{}", STANDARD.encode([7u8; 32]));
+    let mispinned = TlsSettings::from_config(fx.ca_file.to_str(), Some(&wrong)).unwrap();
+    expect_tls_failure(enroll(&url, PUBKEY, &options(mispinned)), "does not match enroll_spki_pin");
+}
+
+#[test]
+fn ct_f19_tls_settings_are_validated() {
+    let mut cfg = ClientConfig { server_public_key_b64: ZERO_KEY.into(), ..ClientConfig::default() };
+    cfg.enroll_url = Some("https://vpn.example.com/enroll".into());
+    cfg.enroll_spki_pin = Some("sha256/not-a-digest".into());
+    cfg.enroll_ca_file = Some("/nonexistent/ca.pem".into());
+    let fields: Vec<String> = validate_config(&cfg).into_iter().map(|i| i.field).collect();
+    assert_eq!(fields, vec!["enroll_spki_pin", "enroll_ca_file"]);
+
+    let opts = EnrollOptions::from_config(&cfg);
+    assert!(matches!(opts, Err(VpnError::Enrollment(_))));
+    cfg.enroll_spki_pin = None;
+    let res = enroll("https://127.0.0.1:1/enroll", PUBKEY, &EnrollOptions::from_config(&cfg).unwrap());
+    match res {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("CA bundle"), "{m}"),
+        other => panic!("expected CA bundle error, got {other:?}"),
+    }
+}
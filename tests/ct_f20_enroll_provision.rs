@@ -0,0 +1,88 @@
+mod common;
+
+use common::{serve_http, test_config, RecordingSystem};
+use std::sync::Arc;
+use std::time::SystemTime;
+use vpn_client::config::{load_client_config, save_client_config, ClientConfig};
+use vpn_client::enroll::{enroll_and_apply, EnrollOptions};
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::VpnError;
+
+const SERVER_KEY: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=";
+
+const ASSIGNMENT: &str = r#"{
+  "address_cidr": "10.8.0.7/32",
+  "server_public_key": "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=",
+  "endpoints": ["127.0.0.1:51820", "127.0.0.2:51820"],
+  "allowed_ips": ["10.8.0.0/24", "192.168.50.0/24"],
+  "dns": ["10.8.0.1"],
+  "keepalive_secs": 15
+}"#;
+
+fn json_reply(body: &str) -> String {
+    format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn fresh_config(enroll_url: String) -> ClientConfig {
+    ClientConfig {
+        interface_name: "wg-ct-f20".into(),
+        server_public_key_b64: String::new(),
+        split_tunnel: false,
+        enroll_url: Some(enroll_url),
+        ..test_config()
+    }
+}
+
+#[test]
+fn ct_f20_enrollment_response_is_merged_and_persisted() {
+    let (base, rx) = serve_http(vec![
+        json_reply(ASSIGNMENT),
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK".into(),
+        json_reply(r#"{"address_cidr": "10.8.0.300/32", "server_public_key": "x", "endpoints": ["vpn:51820"]}"#),
+    ]);
+    let mut cfg = fresh_config(format!("{base}/enroll"));
+    assert!(enroll_and_apply(&mut cfg, &EnrollOptions::default()).unwrap());
+    assert!(rx.recv().unwrap().contains("Accept: application/json\r\n"));
+    assert_eq!(cfg.address_cidr, "10.8.0.7/32");
+    assert_eq!(cfg.server_public_key_b64, SERVER_KEY);
+    assert_eq!(cfg.server_endpoint, "127.0.0.1:51820");
+    assert_eq!(cfg.fallback_endpoints, vec!["127.0.0.2:51820"]);
+    assert_eq!(cfg.allowed_ips, vec!["10.8.0.0/24", "192.168.50.0/24"]);
+    assert_eq!(cfg.dns_servers, vec!["10.8.0.1"]);
+    assert_eq!(cfg.keepalive_secs, 15);
+
+    // Written back like generated keys are, and loads again cleanly.
+    let path = std::env::temp_dir().join(format!("ct_f20_client_{}.toml", std::process::id()));
+    save_client_config(&cfg, Some(path.clone())).unwrap();
+    let reloaded = load_client_config(Some(path.clone())).unwrap();
+    assert_eq!(reloaded.allowed_ips, cfg.allowed_ips);
+    assert_eq!(reloaded.fallback_endpoints, cfg.fallback_endpoints);
+    let _ = std::fs::remove_file(path);
+
+    // A plain-text answer from an older server leaves the config alone.
+    let before = cfg.clone();
+    assert!(!enroll_and_apply(&mut cfg, &EnrollOptions::default()).unwrap());
+    assert_eq!(cfg.address_cidr, before.address_cidr);
+
+    // An assignment that does not validate is rejected without touching the config.
+    match enroll_and_apply(&mut cfg, &EnrollOptions::default()) {
+        Err(VpnError::Enrollment(m)) => assert!(m.contains("address_cidr"), "{m}"),
+        other => panic!("expected enrollment error, got {other:?}"),
+    }
+    assert_eq!(cfg.server_public_key_b64, SERVER_KEY);
+}
+
+#[test]
+fn ct_f20_connect_uses_assigned_settings() {
+    let (base, _rx) = serve_http(vec![json_reply(ASSIGNMENT)]);
+    let cfg = fresh_config(format!("{base}/enroll"));
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let mut tunnel = Tunnel::with_system(cfg, None, sys.clone());
+    tunnel.up().unwrap();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+    assert_eq!(*sys.dns_servers.lock().unwrap(), vec!["10.8.0.1"]);
+    assert_eq!(tunnel.config().address_cidr, "10.8.0.7/32");
+    assert_eq!(tunnel.config().fallback_endpoints, vec!["127.0.0.2:51820"]);
+    tunnel.down().unwrap();
+}
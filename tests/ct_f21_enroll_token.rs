@@ -0,0 +1,86 @@
+mod common;
+
+use common::{reply, serve_http, test_config, RecordingSystem, PUBKEY};
+use std::sync::Arc;
+use std::time::SystemTime;
+use vpn_client::config::ClientConfig;
+use vpn_client::enroll::{enroll, EnrollOptions, TOKEN_ENV};
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::validate::validate_config;
+use vpn_client::VpnError;
+
+const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+
+fn with_token(token: &str) -> EnrollOptions {
+    EnrollOptions { token: Some(token.into()), ..EnrollOptions::default() }
+}
+
+#[test]
+fn ct_f21_token_is_sent_as_bearer_and_rejections_are_distinct() {
+    let (base, rx) = serve_http(vec![
+        OK.into(),
+        OK.into(),
+        reply("401 Unauthorized", "token expired"),
+        reply("403 Forbidden", "invite already used"),
+        reply("409 Conflict", "key registered"),
+    ]);
+    let url = format!("{base}/enroll");
+
+    // From the config, overridden by the environment.
+    let cfg = ClientConfig { enroll_token: Some("cfg-token".into()), ..ClientConfig::default() };
+    enroll(&url, PUBKEY, &EnrollOptions::from_config(&cfg).unwrap()).unwrap();
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer cfg-token\r\n"));
+    std::env::set_var(TOKEN_ENV, "env-token");
+    let opts = EnrollOptions::from_config(&cfg).unwrap();
+    std::env::remove_var(TOKEN_ENV);
+    enroll(&url, PUBKEY, &opts).unwrap();
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer env-token\r\n"));
+
+    let err = enroll(&url, PUBKEY, &EnrollOptions::default()).unwrap_err();
+    assert!(matches!(err, VpnError::EnrollUnauthorized(ref m) if m.contains("token expired")), "{err}");
+    assert!(!rx.recv().unwrap().contains("Authorization"));
+    let err = enroll(&url, PUBKEY, &with_token("invite-123")).unwrap_err();
+    assert!(matches!(err, VpnError::EnrollForbidden(ref m) if m.contains("invite already used")), "{err}");
+    let err = enroll(&url, PUBKEY, &with_token("invite-123")).unwrap_err();
+    assert!(matches!(err, VpnError::KeyAlreadyEnrolled(_)), "{err}");
+    assert_eq!(
+        [VpnError::EnrollUnauthorized(String::new()).exit_code(), VpnError::EnrollForbidden(String::new()).exit_code(),
+         VpnError::KeyAlreadyEnrolled(String::new()).exit_code()],
+        [21, 22, 23]
+    );
+
+    // Header injection is refused before anything is sent.
+    assert!(matches!(enroll(&url, PUBKEY, &with_token("a\r\nX-Evil: 1")), Err(VpnError::Enrollment(_))));
+    let bad = ClientConfig { enroll_token: Some("two words".into()), ..ClientConfig::default() };
+    assert!(validate_config(&bad).iter().any(|i| i.field == "enroll_token"));
+}
+
+#[test]
+fn ct_f21_token_stays_with_its_origin_on_redirect() {
+    let (other, other_rx) = serve_http(vec![OK.into()]);
+    let (base, rx) = serve_http(vec![
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: /v2/enroll\r\nContent-Length: 0\r\n\r\n".into(),
+        format!("HTTP/1.1 307 Temporary Redirect\r\nLocation: {other}/enroll\r\nContent-Length: 0\r\n\r\n"),
+    ]);
+    enroll(&format!("{base}/enroll"), PUBKEY, &with_token("secret")).unwrap();
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer secret"));
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer secret"), "same origin keeps the token");
+    assert!(!other_rx.recv().unwrap().contains("Authorization"), "another origin never sees the token");
+}
+
+#[test]
+fn ct_f21_connect_accepts_an_already_enrolled_key() {
+    let (base, _rx) = serve_http(vec![reply("409 Conflict", "key registered")]);
+    let cfg = ClientConfig {
+        interface_name: "wg-ct-f21".into(),
+        server_public_key_b64: "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=".into(),
+        enroll_url: Some(format!("{base}/enroll")),
+        ..test_config()
+    };
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let mut tunnel = Tunnel::with_system(cfg, None, sys);
+    tunnel.up().unwrap();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+    tunnel.down().unwrap();
+}
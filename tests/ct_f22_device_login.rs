@@ -0,0 +1,134 @@
+mod common;
+
+use common::{serve_http, temp_runtime_dir, test_config, RecordingSystem};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use vpn_client::auth::{self, AuthConfig, Token};
+use vpn_client::config::ClientConfig;
+use vpn_client::tls::TlsSettings;
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::VpnError;
+
+fn json(status: &str, body: &str) -> String {
+    format!("HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn auth_config(base: &str) -> AuthConfig {
+    AuthConfig {
+        device_authorization_url: format!("{base}/device"),
+        token_url: format!("{base}/token"),
+        client_id: "vpn-client".into(),
+        scope: Some("vpn offline_access".into()),
+    }
+}
+
+/** Both tests share one runtime dir: the env var is process-wide. Interface names keep them apart. */
+fn runtime_dir() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| { temp_runtime_dir("ct_f22"); });
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+const DEVICE_CODE: &str = r#"{"device_code":"dev-1","user_code":"WDJB-MJHT","verification_uri":"https://sso.example.com/device",
+  "verification_uri_complete":"https://sso.example.com/device?user_code=WDJB-MJHT","expires_in":60,"interval":0}"#;
+const PENDING: &str = r#"{"error":"authorization_pending"}"#;
+
+#[test]
+fn ct_f22_device_flow_polls_until_approved_and_stores_the_token() {
+    runtime_dir();
+    let (base, rx) = serve_http(vec![
+        json("200 OK", DEVICE_CODE),
+        json("400 Bad Request", PENDING),
+        json("400 Bad Request", PENDING),
+        json("200 OK", r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-1"}"#),
+        json("200 OK", DEVICE_CODE),
+        json("400 Bad Request", r#"{"error":"access_denied","error_description":"not in the vpn-users group"}"#),
+        json("200 OK", &DEVICE_CODE.replace("https://sso.example.com/device?", "file:///etc/passwd?")),
+    ]);
+    let cfg = auth_config(&base);
+    // A token file left with loose permissions is replaced, not reused.
+    let stale = auth::token_path("wg-ct-f22");
+    std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+    std::fs::write(&stale, "{}").unwrap();
+    let shown = Mutex::new(Vec::new());
+    let token = auth::login(&cfg, "wg-ct-f22", &TlsSettings::default(), &|code| {
+        shown.lock().unwrap().push((code.user_code.clone(), code.browser_url().to_string()));
+    }).unwrap();
+    assert_eq!(token.access_token.expose(), "at-1");
+    assert_eq!(token.refresh_token.as_ref().map(|t| t.expose()), Some("rt-1"));
+    assert!(token.expires_at.unwrap() > now() + 3000);
+    assert_eq!(*shown.lock().unwrap(), [("WDJB-MJHT".to_string(), "https://sso.example.com/device?user_code=WDJB-MJHT".to_string())]);
+
+    let device_req = rx.recv().unwrap();
+    assert!(device_req.starts_with("POST /device "));
+    assert!(device_req.contains("Content-Type: application/x-www-form-urlencoded\r\n"));
+    assert!(device_req.ends_with("client_id=vpn-client&scope=vpn%20offline_access"), "{device_req}");
+    for _ in 0..3 {
+        let poll = rx.recv().unwrap();
+        assert!(poll.starts_with("POST /token "));
+        assert!(poll.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code&device_code=dev-1"), "{poll}");
+    }
+
+    let stored = auth::load_token("wg-ct-f22").unwrap();
+    assert_eq!((stored.access_token.expose(), stored.expires_at), (token.access_token.expose(), token.expires_at));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(auth::token_path("wg-ct-f22")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0, "token file must not be readable by others");
+    }
+
+    let err = auth::login(&cfg, "wg-ct-f22-denied", &TlsSettings::default(), &|_| {}).unwrap_err();
+    assert!(matches!(err, VpnError::Auth(ref m) if m.contains("denied")), "{err}");
+    assert_eq!(err.exit_code(), 24);
+
+    // The verification URI comes from the server and is opened in the browser.
+    let err = auth::login(&cfg, "wg-ct-f22-uri", &TlsSettings::default(), &|_| panic!("shown")).unwrap_err();
+    assert!(matches!(err, VpnError::Auth(ref m) if m.contains("verification URI")), "{err}");
+}
+
+#[test]
+fn ct_f22_expiring_token_is_refreshed_before_enrollment_and_reconnects() {
+    runtime_dir();
+    let refreshed = |at: &str| json("200 OK", &format!(r#"{{"access_token":"{at}","expires_in":3600}}"#));
+    let enrolled = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string();
+    let (base, rx) = serve_http(vec![refreshed("at-2"), enrolled.clone(), refreshed("at-3"), enrolled]);
+
+    let ifname = "wg-ct-f22r";
+    auth::save_token(ifname, &Token { access_token: "at-1".into(), refresh_token: Some("rt-1".into()), expires_at: Some(now() + 5) }).unwrap();
+    let cfg = ClientConfig {
+        enroll_url: Some(format!("{base}/enroll")),
+        auth: Some(auth_config(&base)),
+        ..test_config()
+    };
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let mut tunnel = Tunnel::with_system(cfg, Some(ifname.into()), sys.clone());
+    tunnel.up().unwrap();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+
+    let refresh = rx.recv().unwrap();
+    assert!(refresh.ends_with("grant_type=refresh_token&refresh_token=rt-1&client_id=vpn-client"), "{refresh}");
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer at-2\r\n"));
+    let stored = auth::load_token(ifname).unwrap();
+    assert_eq!(stored.access_token.expose(), "at-2");
+    assert_eq!(stored.refresh_token.as_ref().map(|t| t.expose()), Some("rt-1"), "refresh token kept when not rotated");
+
+    // The token is about to expire again when the tunnel goes stale: refresh before reconnecting.
+    auth::save_token(ifname, &Token { expires_at: Some(now()), ..stored }).unwrap();
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now() - Duration::from_secs(240));
+    tunnel.poll();
+    assert_eq!(tunnel.state(), TunnelState::Reconnecting);
+    assert!(rx.recv().unwrap().contains("refresh_token=rt-1"));
+    assert!(rx.recv().unwrap().contains("Authorization: Bearer at-3\r\n"));
+    tunnel.down().unwrap();
+
+    // Nothing stored and no way to log in from the tunnel: a clear error.
+    let mut cfg = tunnel.config().clone();
+    cfg.interface_name = "wg-ct-f22n".into();
+    let mut fresh = Tunnel::with_system(cfg, None, Arc::new(RecordingSystem::default()));
+    assert!(matches!(fresh.up(), Err(VpnError::Auth(ref m)) if m.contains("vpn-client login")));
+}
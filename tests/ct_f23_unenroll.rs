@@ -0,0 +1,97 @@
+mod common;
+
+use common::{reply, serve_http, temp_dir, PUBKEY};
+use vpn_client::config::{derive_public_key_b64, ensure_client_keys, load_client_config, save_client_config, set_key_passphrase, ClientConfig};
+use vpn_client::enroll::{retry_pending_unenroll, unenroll, EnrollOptions, PendingUnenroll};
+use vpn_client::keys::{self, SecretKey};
+use vpn_client::validate::validate_config;
+use vpn_client::VpnError;
+
+/** A local address nothing listens on. */
+fn closed_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}/enroll", listener.local_addr().unwrap())
+}
+
+#[test]
+fn ct_f23_unenroll_sends_an_authenticated_delete() {
+    let (base, rx) = serve_http(vec![
+        reply("204 No Content", ""),
+        reply("404 Not Found", "unknown key"),
+        reply("401 Unauthorized", "token expired"),
+        reply("503 Service Unavailable", ""),
+    ]);
+    let url = format!("{base}/enroll");
+    let opts = EnrollOptions { token: Some("secret".into()), ..EnrollOptions::default() };
+    unenroll(&url, PUBKEY, &opts).unwrap();
+    let req = rx.recv().unwrap();
+    assert!(req.starts_with("DELETE /enroll "), "{req}");
+    assert!(req.contains("Authorization: Bearer secret\r\n"));
+    assert!(req.ends_with(PUBKEY));
+
+    unenroll(&url, PUBKEY, &opts).expect("a key the server does not know is already gone");
+    assert!(matches!(unenroll(&url, PUBKEY, &opts), Err(VpnError::EnrollUnauthorized(_))));
+    assert!(matches!(unenroll(&url, PUBKEY, &opts), Err(VpnError::Connectivity(_))), "503 is worth retrying");
+    assert!(matches!(unenroll(&closed_url(), PUBKEY, &opts), Err(VpnError::Connectivity(_))));
+}
+
+#[test]
+fn ct_f23_pending_deregistrations_are_retried_and_persisted() {
+    let (base, rx) = serve_http(vec![reply("200 OK", ""), reply("400 Bad Request", "malformed key")]);
+    let offline = PendingUnenroll { url: closed_url(), public_key: "offline".into() };
+    let mut cfg = ClientConfig {
+        pending_unenroll: vec![
+            PendingUnenroll { url: format!("{base}/enroll"), public_key: PUBKEY.into() },
+            offline.clone(),
+            PendingUnenroll { url: format!("{base}/enroll"), public_key: "refused".into() },
+        ],
+        ..ClientConfig::default()
+    };
+
+    let outcomes = retry_pending_unenroll(&mut cfg, &EnrollOptions::default());
+    assert!(outcomes[0].1.is_ok());
+    assert!(rx.recv().unwrap().ends_with(PUBKEY));
+    assert!(matches!(outcomes[1].1, Err(VpnError::Connectivity(_))));
+    assert!(matches!(outcomes[2].1, Err(VpnError::Enrollment(_))));
+    assert_eq!(cfg.pending_unenroll, vec![offline], "only the unreachable one is kept");
+
+    let path = std::env::temp_dir().join(format!("ct_f23_client_{}.toml", std::process::id()));
+    save_client_config(&cfg, Some(path.clone())).unwrap();
+    assert_eq!(load_client_config(Some(path.clone())).unwrap().pending_unenroll, cfg.pending_unenroll);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn ct_f23_deregistered_key_is_removed_from_its_key_file() {
+    let dir = temp_dir("ct_f23_keyfile");
+    let path = dir.join("client.toml");
+    let (base, _rx) = serve_http(vec![reply("204 No Content", ""), reply("204 No Content", "")]);
+    for passphrase in [None, Some("s3cret")] {
+        let cfg = ClientConfig { private_key_file: Some("client.key".into()), ..ClientConfig::default() };
+        let cfg = ensure_client_keys(cfg, Some(path.clone())).unwrap();
+        set_key_passphrase(cfg, Some(path.clone()), passphrase.map(SecretKey::from)).unwrap();
+
+        // what `vpn-client unenroll` does
+        let mut cfg = load_client_config(Some(path.clone())).unwrap();
+        keys::unlock_key(&mut cfg, &|| Ok("s3cret".into())).unwrap();
+        let public_key = derive_public_key_b64(cfg.client_private_key_b64.as_ref().unwrap().expose()).unwrap();
+        unenroll(&format!("{base}/enroll"), &public_key, &EnrollOptions::default()).unwrap();
+        cfg.forget_key();
+        save_client_config(&cfg, Some(path.clone())).unwrap();
+
+        assert!(!dir.join("client.key").exists(), "{passphrase:?}");
+        let reloaded = load_client_config(Some(path.clone())).unwrap();
+        assert!(reloaded.client_private_key_b64.is_none() && reloaded.sealed_key.is_none(), "{passphrase:?}");
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ct_f23_unenroll_url_defaults_to_enroll_url() {
+    let mut cfg = ClientConfig { enroll_url: Some("https://vpn.example.com/enroll".into()), ..ClientConfig::default() };
+    assert_eq!(cfg.effective_unenroll_url(), Some("https://vpn.example.com/enroll"));
+    cfg.unenroll_url = Some("https://vpn.example.com/devices/remove".into());
+    assert_eq!(cfg.effective_unenroll_url(), Some("https://vpn.example.com/devices/remove"));
+    cfg.unenroll_url = Some("ftp://vpn".into());
+    assert!(validate_config(&cfg).iter().any(|i| i.field == "unenroll_url"));
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use base64::Engine as _;
+use common::{connect_daemon, serve_http, temp_runtime_dir, test_config, RecordingSystem, ZERO_KEY};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::SystemTime;
+use std::thread;
+use vpn_client::config::{derive_public_key_b64, save_client_config, ClientConfig};
+use vpn_client::daemon::{Daemon, Reply, Request};
+use vpn_client::enroll::{rotate_key, EnrollOptions};
+use vpn_client::keys::{self, rotation_warning};
+use vpn_client::tunnel::{Tunnel, TunnelState};
+use vpn_client::VpnError;
+
+const OLD_KEY: &str = ZERO_KEY;
+const SERVER_SECRET: [u8; 32] = [7; 32];
+
+fn server_public() -> String {
+    let secret = x25519_dalek::StaticSecret::from(SERVER_SECRET);
+    base64::engine::general_purpose::STANDARD.encode(x25519_dalek::PublicKey::from(&secret).as_bytes())
+}
+
+fn header<'a>(req: &'a str, name: &str) -> Option<&'a str> {
+    req.lines().find_map(|l| l.strip_prefix(&format!("{name}: ")))
+}
+
+fn config(enroll_url: Option<String>) -> ClientConfig {
+    ClientConfig {
+        server_public_key_b64: server_public(),
+        client_key_created: Some("2026-01-01T00:00:00Z".into()),
+        enroll_url,
+        ..test_config()
+    }
+}
+
+#[test]
+fn ct_f24_new_key_is_enrolled_with_proof_of_the_old_one() {
+    let (base, rx) = serve_http(vec![
+        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".into(),
+        "HTTP/1.1 401 Unauthorized\r\nContent-Length: 9\r\n\r\nbad proof".into(),
+    ]);
+    let cfg = config(Some(format!("{base}/enroll")));
+    let rotated = rotate_key(&cfg, &EnrollOptions::default()).unwrap();
+    let new_key = rotated.client_private_key_b64.clone().unwrap();
+    assert_ne!(new_key.expose(), OLD_KEY);
+    assert_ne!(rotated.client_key_created, cfg.client_key_created);
+    keys::parse_created(rotated.client_key_created.as_deref().unwrap()).unwrap();
+
+    let req = rx.recv().unwrap();
+    let new_public = derive_public_key_b64(new_key.expose()).unwrap();
+    assert!(req.ends_with(&new_public), "the new public key is enrolled");
+    assert_eq!(header(&req, "X-Previous-Key"), Some(derive_public_key_b64(OLD_KEY).unwrap().as_str()));
+    // The server recomputes the proof from its own secret and the old public key.
+    let old_public = base64::engine::general_purpose::STANDARD.decode(derive_public_key_b64(OLD_KEY).unwrap()).unwrap();
+    let old_public = x25519_dalek::PublicKey::from(<[u8; 32]>::try_from(old_public).unwrap());
+    let shared = x25519_dalek::StaticSecret::from(SERVER_SECRET).diffie_hellman(&old_public);
+    let mut mac = Hmac::<Sha256>::new_from_slice(shared.as_bytes()).unwrap();
+    mac.update(b"vpn-client key rotation v1\n");
+    mac.update(new_public.as_bytes());
+    let expected = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+    assert_eq!(header(&req, "X-Key-Proof"), Some(expected.as_str()));
+
+    assert!(matches!(rotate_key(&cfg, &EnrollOptions::default()), Err(VpnError::EnrollUnauthorized(_))));
+    let no_server = ClientConfig { server_public_key_b64: String::new(), ..cfg };
+    assert!(matches!(rotate_key(&no_server, &EnrollOptions::default()), Err(VpnError::Enrollment(m)) if m.contains("no token")));
+}
+
+#[test]
+fn ct_f24_running_tunnel_switches_key_in_place() {
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let cfg = config(None);
+    let mut tunnel = Tunnel::with_system(cfg.clone(), Some("wg-ct-f24".into()), sys.clone());
+    assert!(tunnel.swap_key(cfg.clone()).is_err(), "nothing to swap before the tunnel is up");
+    tunnel.up().unwrap();
+
+    let new_key = keys::generate_private_key();
+    tunnel.swap_key(ClientConfig { client_private_key_b64: Some(new_key.clone()), ..cfg }).unwrap();
+    assert_eq!(tunnel.state(), TunnelState::Connected);
+    assert_eq!(*sys.interface_keys.lock().unwrap(), [OLD_KEY.to_string(), new_key.expose().to_string()]);
+    assert!(sys.undone().is_empty(), "nothing was torn down");
+    assert_eq!(tunnel.config().client_private_key_b64.as_ref().map(|k| k.expose()), Some(new_key.expose()));
+    tunnel.down().unwrap();
+}
+
+#[test]
+fn ct_f24_daemon_swaps_key_from_the_saved_config() {
+    let dir = temp_runtime_dir("ct_f24");
+    let sock = dir.join("vpn-client.sock");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("client.toml");
+    let sys = Arc::new(RecordingSystem::default());
+    *sys.handshake.lock().unwrap() = Some(SystemTime::now());
+    let cfg = ClientConfig { interface_name: "wg-ct-f24d".into(), ..config(None) };
+    save_client_config(&cfg, Some(path.clone())).unwrap();
+    let daemon = Daemon::with_system(cfg.clone(), Some(path.clone()), sys.clone());
+    let stop = daemon.stop_handle();
+    let server = {
+        let (daemon, sock) = (daemon.clone(), sock.clone());
+        thread::spawn(move || daemon.serve(&sock, None))
+    };
+    let mut client = connect_daemon(&sock);
+    assert!(matches!(client.request(&Request::Up { ifname: None, force: false }).unwrap(), Reply::Ok { .. }));
+
+    let new_key = keys::generate_private_key();
+    save_client_config(&ClientConfig { client_private_key_b64: Some(new_key.clone()), ..cfg }, Some(path.clone())).unwrap();
+    // A config others can read is refused before the key is loaded from it.
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(client.request(&Request::SwapKey), Err(VpnError::Daemon { code: 25, .. })));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    match client.request(&Request::SwapKey).unwrap() {
+        Reply::Ok { message } => assert_eq!(message, "key swapped on 1 tunnel(s)"),
+        other => panic!("unexpected reply {other:?}"),
+    }
+    assert_eq!(sys.interface_keys.lock().unwrap().last().map(String::as_str), Some(new_key.expose()));
+    assert!(sys.undone().is_empty(), "the tunnel was not restarted");
+
+    stop.store(true, Ordering::SeqCst);
+    server.join().unwrap().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ct_f24_status_warns_about_an_overdue_key() {
+    let now = keys::parse_created("2026-04-02T00:00:00Z").unwrap();
+    let mut cfg = config(None);
+    assert!(rotation_warning(&cfg, now).unwrap().contains("91 days old"));
+    cfg.client_key_created = Some("2026-03-01T00:00:00Z".into());
+    assert_eq!(rotation_warning(&cfg, now), None);
+    cfg.key_rotation_days = 30;
+    assert!(rotation_warning(&cfg, now).is_some());
+    cfg.key_rotation_days = 0;
+    assert_eq!(rotation_warning(&cfg, now), None, "0 disables the warning");
+
+    cfg.client_key_created = Some("last tuesday".into());
+    assert!(vpn_client::validate::validate_config(&cfg).iter().any(|i| i.field == "client_key_created"));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{temp_dir, ZERO_KEY};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use vpn_client::config::{
+    check_config_file, check_file_permissions, ensure_client_keys, load_checked_config, load_client_config, migrate_private_key, save_client_config, ClientConfig,
+};
+use vpn_client::VpnError;
+
+fn mode(path: &PathBuf) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn ct_f25_key_lives_in_its_own_private_file() {
+    let dir = temp_dir("ct_f25_file");
+    let path = dir.join("client.toml");
+    let cfg = ClientConfig { private_key_file: Some("client.key".into()), ..ClientConfig::default() };
+    let cfg = ensure_client_keys(cfg, Some(path.clone())).unwrap();
+    let key = cfg.client_private_key_b64.clone().unwrap();
+
+    assert!(!fs::read_to_string(&path).unwrap().contains(key.expose()), "the key is not written into the config");
+    assert_eq!(fs::read_to_string(dir.join("client.key")).unwrap().trim(), key.expose());
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(mode(&dir.join("client.key")), 0o600);
+    let loaded = load_client_config(Some(path.clone())).unwrap();
+    assert_eq!(loaded.client_private_key_b64.as_ref().map(|k| k.expose()), Some(key.expose()));
+    check_file_permissions(&loaded, Some(&path)).unwrap();
+
+    fs::set_permissions(dir.join("client.key"), fs::Permissions::from_mode(0o640)).unwrap();
+    let err = check_file_permissions(&loaded, Some(&path)).unwrap_err();
+    assert!(matches!(err, VpnError::InsecurePermissions(ref m) if m.contains("client.key") && m.contains("0640")), "{err}");
+    assert_eq!(err.exit_code(), 25);
+    fs::set_permissions(dir.join("client.key"), fs::Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(matches!(check_file_permissions(&loaded, Some(&path)), Err(VpnError::InsecurePermissions(_))));
+
+    // Commands that use the key refuse before reading it.
+    assert!(matches!(load_checked_config(Some(path.clone()), &|_| {}), Err(VpnError::InsecurePermissions(ref m)) if m.contains("client.toml")));
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(dir.join("client.key"), fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(matches!(load_checked_config(Some(path.clone()), &|_| {}), Err(VpnError::InsecurePermissions(ref m)) if m.contains("client.key")));
+    fs::set_permissions(dir.join("client.key"), fs::Permissions::from_mode(0o600)).unwrap();
+    assert_eq!(load_checked_config(Some(path.clone()), &|_| {}).unwrap().client_private_key_b64.as_ref().map(|k| k.expose()), Some(key.expose()));
+
+    // Saving again (e.g. after enrollment) keeps both files private.
+    save_client_config(&loaded, Some(path.clone())).unwrap();
+    assert_eq!(mode(&path), 0o600);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ct_f25_inline_keys_are_migrated_out_of_the_config() {
+    let dir = temp_dir("ct_f25_migrate");
+    let path = dir.join("client.toml");
+    let inline = ClientConfig { client_private_key_b64: Some(ZERO_KEY.into()), ..ClientConfig::default() };
+    fs::write(&path, toml::to_string_pretty(&inline).unwrap()).unwrap();
+    assert!(matches!(migrate_private_key(Some(path.clone()), None), Err(VpnError::InsecurePermissions(_))));
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+    fs::write(dir.join("client.key"), "something else").unwrap();
+    assert!(matches!(migrate_private_key(Some(path.clone()), None), Err(VpnError::Io(ref e)) if e.kind() == std::io::ErrorKind::AlreadyExists));
+    fs::remove_file(dir.join("client.key")).unwrap();
+
+    assert_eq!(migrate_private_key(Some(path.clone()), None).unwrap(), Some(dir.join("client.key")));
+    assert!(!fs::read_to_string(&path).unwrap().contains(ZERO_KEY));
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(mode(&dir.join("client.key")), 0o600);
+    let loaded = load_client_config(Some(path.clone())).unwrap();
+    assert_eq!(loaded.private_key_file.as_deref(), Some("client.key"));
+    assert_eq!(loaded.client_private_key_b64.as_ref().map(|k| k.expose()), Some(ZERO_KEY));
+    assert_eq!(migrate_private_key(Some(path.clone()), None).unwrap(), None, "nothing left to migrate");
+
+    // A key both inline and in a file is ambiguous.
+    let both = format!("client_private_key_b64 = \"{ZERO_KEY}\"\n{}", fs::read_to_string(&path).unwrap());
+    fs::write(&path, both).unwrap();
+    let fields: Vec<String> = check_config_file(&path).unwrap().issues.into_iter().map(|i| i.field).collect();
+    assert_eq!(fields, ["client_private_key_b64"]);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{temp_dir, test_config, ZERO_KEY};
+use std::fs;
+use vpn_client::config::{ensure_client_keys, load_client_config, save_client_config, set_key_passphrase, ClientConfig};
+use vpn_client::keys::{self, SealedKey, SecretKey};
+use vpn_client::VpnError;
+
+fn answer(passphrase: &'static str) -> impl Fn() -> Result<SecretKey, VpnError> {
+    move || Ok(passphrase.into())
+}
+
+#[test]
+fn ct_f26_sealed_key_opens_only_with_its_passphrase() {
+    let sealed = SealedKey::seal(ZERO_KEY, "correct horse").unwrap();
+    assert_eq!((sealed.version, sealed.kdf.as_str()), (1, "argon2id"));
+    assert!(!sealed.ciphertext.contains(ZERO_KEY));
+    assert_eq!(sealed.open("correct horse").unwrap().expose(), ZERO_KEY);
+    assert_ne!(SealedKey::seal(ZERO_KEY, "correct horse").unwrap().nonce, sealed.nonce, "fresh nonce per seal");
+
+    let err = sealed.open("battery staple").unwrap_err();
+    assert!(matches!(err, VpnError::Passphrase(ref m) if m.contains("wrong passphrase")), "{err}");
+    assert_eq!(err.exit_code(), 26);
+    for costly in [SealedKey { m_cost: 4 * 1024 * 1024, ..sealed.clone() }, SealedKey { t_cost: 1000, ..sealed.clone() }, SealedKey { p_cost: 64, ..sealed.clone() }] {
+        let err = costly.open("correct horse").unwrap_err();
+        assert!(matches!(err, VpnError::Passphrase(ref m) if m.contains("out of range")), "{err}");
+    }
+    let future = SealedKey { version: 2, ..sealed };
+    assert!(matches!(future.open("correct horse"), Err(VpnError::Passphrase(m)) if m.contains("version 2")));
+}
+
+#[test]
+fn ct_f26_encrypted_key_file_round_trip() {
+    let dir = temp_dir("ct_f26_file");
+    let path = dir.join("client.toml");
+    let cfg = ClientConfig { private_key_file: Some("client.key".into()), ..test_config() };
+    let cfg = set_key_passphrase(cfg, Some(path.clone()), Some("s3cret".into())).unwrap();
+    drop(cfg);
+    let on_disk = fs::read_to_string(dir.join("client.key")).unwrap();
+    assert!(on_disk.trim_start().starts_with('{') && !on_disk.contains(ZERO_KEY), "{on_disk}");
+
+    let mut locked = load_client_config(Some(path.clone())).unwrap();
+    assert!(locked.client_private_key_b64.is_none());
+    assert!(locked.sealed_key.is_some());
+    let err = ensure_client_keys(locked.clone(), Some(path.clone())).unwrap_err();
+    assert!(matches!(err, VpnError::Passphrase(_)), "a locked key is never replaced by a new one");
+    assert!(matches!(keys::unlock_key(&mut locked.clone(), &keys::no_prompt), Err(VpnError::Passphrase(_))));
+    assert!(matches!(keys::unlock_key(&mut locked.clone(), &answer("wrong")), Err(VpnError::Passphrase(_))));
+
+    keys::unlock_key(&mut locked, &answer("s3cret")).unwrap();
+    assert_eq!(locked.client_private_key_b64.as_ref().map(|k| k.expose()), Some(ZERO_KEY));
+
+    // A rotated key is saved encrypted with the same passphrase.
+    let new_key = keys::generate_private_key();
+    save_client_config(&ClientConfig { client_private_key_b64: Some(new_key.clone()), ..locked.clone() }, Some(path.clone())).unwrap();
+    let mut reloaded = load_client_config(Some(path.clone())).unwrap();
+    keys::unlock_key(&mut reloaded, &answer("s3cret")).unwrap();
+    assert_eq!(reloaded.client_private_key_b64.as_ref().map(|k| k.expose()), Some(new_key.expose()));
+
+    // The daemon takes the passphrase from the environment.
+    let mut from_env = load_client_config(Some(path.clone())).unwrap();
+    std::env::set_var(keys::PASSPHRASE_ENV, "s3cret");
+    let res = keys::unlock_key(&mut from_env, &keys::no_prompt);
+    std::env::remove_var(keys::PASSPHRASE_ENV);
+    res.unwrap();
+    assert_eq!(from_env.client_private_key_b64.as_ref().map(|k| k.expose()), Some(new_key.expose()));
+
+    set_key_passphrase(reloaded, Some(path.clone()), None).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("client.key")).unwrap().trim(), new_key.expose());
+    assert_eq!(load_client_config(Some(path)).unwrap().client_private_key_b64.as_ref().map(|k| k.expose()), Some(new_key.expose()));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ct_f26_only_a_key_file_can_be_encrypted() {
+    let inline = test_config();
+    let path = std::env::temp_dir().join(format!("ct_f26_inline_{}.toml", std::process::id()));
+    assert!(matches!(set_key_passphrase(inline, Some(path.clone()), Some("x".into())), Err(VpnError::Passphrase(m)) if m.contains("key migrate")));
+    assert!(!path.exists());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{test_config, ZERO_KEY};
+use vpn_client::auth::Token;
+use vpn_client::config::ClientConfig;
+use vpn_client::enroll::EnrollOptions;
+use vpn_client::keys::{self, SecretKey};
+use zeroize::Zeroize;
+
+#[test]
+fn ct_f27_debug_output_never_shows_key_material() {
+    // not test_config(): its server key is the same zero key, and public
+    let cfg = ClientConfig { client_private_key_b64: Some(ZERO_KEY.into()), key_passphrase: Some("hunter2".into()), ..ClientConfig::default() };
+    let printed = format!("{cfg:?}");
+    assert!(!printed.contains(ZERO_KEY) && !printed.contains("hunter2"), "{printed}");
+    assert!(printed.contains("client_private_key_b64: Some(SecretKey(<redacted>))"));
+    assert_eq!(format!("{:?}", keys::generate_private_key()), "SecretKey(<redacted>)");
+}
+
+#[test]
+fn ct_f27_debug_output_never_shows_tokens() {
+    let cfg = ClientConfig { enroll_token: Some("enroll-tok".into()), ..ClientConfig::default() };
+    let opts = EnrollOptions { token: Some("invite-tok".into()), ..EnrollOptions::default() };
+    let token = Token { access_token: "access-tok".into(), refresh_token: Some("refresh-tok".into()), expires_at: None };
+    let printed = format!("{cfg:?} {opts:?} {token:?}");
+    for secret in ["enroll-tok", "invite-tok", "access-tok", "refresh-tok"] {
+        assert!(!printed.contains(secret), "{secret} in {printed}");
+    }
+    assert!(printed.contains("refresh_token: Some(SecretKey(<redacted>))"));
+
+    // Stored as plain strings all the same.
+    let json = serde_json::to_string(&token).unwrap();
+    assert!(json.contains(r#""access_token":"access-tok""#) && json.contains(r#""refresh_token":"refresh-tok""#), "{json}");
+}
+
+#[test]
+fn ct_f27_secret_key_is_stored_as_a_plain_string_and_wiped() {
+    let cfg = ClientConfig { key_passphrase: Some("hunter2".into()), ..test_config() };
+    let text = toml::to_string_pretty(&cfg).unwrap();
+    assert!(text.contains(&format!("client_private_key_b64 = \"{ZERO_KEY}\"")));
+    assert!(!text.contains("hunter2"), "the passphrase is never written");
+    let parsed: ClientConfig = toml::from_str(&text).unwrap();
+    assert_eq!(parsed.client_private_key_b64.as_ref().map(|k| k.expose()), Some(ZERO_KEY));
+
+    let mut key = SecretKey::from(ZERO_KEY);
+    assert!(key.matches(&ZERO_KEY.into()));
+    assert!(!key.matches(&"BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".into()) && !key.matches(&"AAAA".into()));
+    key.zeroize();
+    assert_eq!(key.expose(), "");
+}